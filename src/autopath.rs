@@ -0,0 +1,252 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use bevy::utils::FloatOrd;
+
+use super::*;
+
+// Searches run inside a single frame, so keep them short
+const MAX_EXPANSIONS: usize = 10_000;
+const SEARCH_MARGIN: i32 = 12;
+const TURN_PENALTY: f32 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MoveKind {
+    Straight,
+    Turn,
+    Bend,
+}
+
+// A single track segment the router can lay from a tile, relative to that tile
+struct Move {
+    offset: TileVec,
+    facing: Octant,
+    kind: MoveKind,
+    cost: f32,
+    footprint: Vec<TileVec>,
+}
+
+fn moves_from(params: &TrackParams, facing: Octant) -> Vec<Move> {
+    let mut targets = vec![(
+        octant_to_unit(facing).round().as_ivec2(),
+        facing,
+        MoveKind::Straight,
+    )];
+    for dir in [-1., 1.] {
+        let turned = facing.turn(dir);
+        let turn = params.get_turn(facing, dir).as_ivec2();
+        let back = params.get_turn(turned, -dir).as_ivec2();
+        targets.push((turn, turned, MoveKind::Turn));
+        targets.push((turn + back, facing, MoveKind::Bend));
+    }
+
+    let origin = TrackPos::new((0, 0), facing);
+    targets
+        .into_iter()
+        .map(|(offset, end_facing, kind)| {
+            let segment = params.segment(origin, (offset, end_facing).into());
+            let length = segment.curve().approximate_length(0.1) / TILE_SIZE;
+            let cost = match kind {
+                MoveKind::Straight => length,
+                MoveKind::Turn | MoveKind::Bend => length + TURN_PENALTY,
+            };
+            Move {
+                offset,
+                facing: end_facing,
+                kind,
+                cost,
+                footprint: segment.tiles().into_iter().map(tile_to_vec).collect(),
+            }
+        })
+        .collect()
+}
+
+// Previous position and the index of the move taken from it
+type CameFrom = HashMap<TrackPos, (TrackPos, usize)>;
+
+// Tiles the route to a position already covers, apart from the move that arrived there
+fn route_footprint(came_from: &CameFrom, moves: &[Vec<Move>], end: TrackPos) -> HashSet<TileVec> {
+    let mut tiles = HashSet::new();
+    let mut current = end;
+    let mut last = true;
+    while let Some((prev, index)) = came_from.get(&current) {
+        if !last {
+            let origin = tile_to_vec(prev.tile);
+            let m = &moves[prev.facing.0 as usize][*index];
+            tiles.extend(m.footprint.iter().map(|offset| origin + *offset));
+        }
+        last = false;
+        current = *prev;
+    }
+    tiles
+}
+
+// Merge runs of single tile straights into one segment
+fn reconstruct(came_from: &CameFrom, moves: &[Vec<Move>], end: TrackPos) -> Vec<TrackPos> {
+    let mut steps = Vec::new();
+    let mut current = end;
+    while let Some((prev, index)) = came_from.get(&current) {
+        steps.push((current, moves[prev.facing.0 as usize][*index].kind));
+        current = *prev;
+    }
+    steps.reverse();
+
+    let mut route = vec![current];
+    for (i, (pos, kind)) in steps.iter().enumerate() {
+        let next_straight = steps.get(i + 1).map(|(_, k)| *k) == Some(MoveKind::Straight);
+        if *kind != MoveKind::Straight || !next_straight {
+            route.push(*pos);
+        }
+    }
+    route
+}
+
+// A* over tiles and facings using straights, 45 degree turns and S-bends of the given radius
+pub fn find_route<F>(
+    params: &TrackParams,
+    start: TrackPos,
+    end: TrackPos,
+    blocked: F,
+) -> Option<Vec<TrackPos>>
+where
+    F: Fn(TileIndex) -> bool,
+{
+    if start.tile == end.tile {
+        return None;
+    }
+
+    let moves: Vec<Vec<Move>> = (0..8usize).map(|o| moves_from(params, o.into())).collect();
+
+    let start_vec = tile_to_vec(start.tile);
+    let end_vec = tile_to_vec(end.tile);
+    let margin = IVec2::splat(SEARCH_MARGIN + params.radius.ceil() as i32 * 2);
+    let min = start_vec.min(end_vec) - margin;
+    let max = start_vec.max(end_vec) + margin;
+    let heuristic = |tile: TileIndex| tile_to_vec(tile).as_vec2().distance(end_vec.as_vec2());
+
+    let mut open = BinaryHeap::new();
+    let mut closed = HashSet::new();
+    let mut costs: HashMap<TrackPos, f32> = HashMap::new();
+    let mut came_from: CameFrom = HashMap::new();
+
+    costs.insert(start, 0.);
+    open.push(Reverse((FloatOrd(heuristic(start.tile)), start)));
+
+    while let Some(Reverse((_, pos))) = open.pop() {
+        if pos == end {
+            return Some(reconstruct(&came_from, &moves, end));
+        }
+        if !closed.insert(pos) {
+            continue;
+        }
+        if closed.len() > MAX_EXPANSIONS {
+            break;
+        }
+
+        let cost = costs[&pos];
+        let tile = tile_to_vec(pos.tile);
+        let own = route_footprint(&came_from, &moves, pos);
+        for (index, m) in moves[pos.facing.0 as usize].iter().enumerate() {
+            let next_tile = tile + m.offset;
+            if next_tile.cmplt(min).any() || next_tile.cmpgt(max).any() {
+                continue;
+            }
+            let next = TrackPos::from((next_tile, m.facing));
+            if closed.contains(&next) {
+                continue;
+            }
+
+            // Only the route's own endpoints may touch existing track
+            let hits = m
+                .footprint
+                .iter()
+                .map(|offset| tile + *offset)
+                .map(|t| (t.x, t.y))
+                .filter(|t| *t != start.tile && *t != end.tile)
+                .any(&blocked);
+            // Nor may it cross itself
+            let crosses = m
+                .footprint
+                .iter()
+                .any(|offset| *offset != IVec2::ZERO && own.contains(&(tile + *offset)));
+            if hits || crosses {
+                continue;
+            }
+
+            let next_cost = cost + m.cost;
+            if costs.get(&next).map_or(true, |c| next_cost < *c) {
+                costs.insert(next, next_cost);
+                came_from.insert(next, (pos, index));
+                open.push(Reverse((FloatOrd(next_cost + heuristic(next.tile)), next)));
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params() -> TrackParams {
+        TrackParams {
+            radius: 2.5,
+            track_count: 1,
+            spacing: 2,
+            parallel_left: false,
+            full_path: true,
+            layer: TrackLayer::Ground,
+            geometry: TrackGeometry::Standard,
+            one_way: false,
+        }
+    }
+
+    fn footprints(params: &TrackParams, route: &[TrackPos]) -> Vec<HashSet<TileIndex>> {
+        route
+            .windows(2)
+            .map(|pair| {
+                params
+                    .segment(pair[0], pair[1])
+                    .tiles()
+                    .into_iter()
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn straight_route_is_one_segment() {
+        let start = TrackPos::new((0, 0), Octant(0));
+        let end = TrackPos::new((0, 6), Octant(0));
+        let route = find_route(&params(), start, end, |_| false).unwrap();
+        assert_eq!(route, vec![start, end]);
+    }
+
+    #[test]
+    fn route_goes_around_blocked_tiles() {
+        let params = params();
+        let start = TrackPos::new((0, 0), Octant(0));
+        let end = TrackPos::new((0, 12), Octant(0));
+        let blocked = |(x, y): TileIndex| x == 0 && (4..8).contains(&y);
+        let route = find_route(&params, start, end, blocked).unwrap();
+        for tiles in footprints(&params, &route) {
+            assert!(!tiles.iter().any(|tile| blocked(*tile)));
+        }
+    }
+
+    #[test]
+    fn route_never_crosses_itself() {
+        let params = params();
+        // Ending below the start facing down forces the route to loop back
+        let start = TrackPos::new((0, 0), Octant(0));
+        let end = TrackPos::new((0, -6), Octant(4));
+        let route = find_route(&params, start, end, |_| false).unwrap();
+        let tiles = footprints(&params, &route);
+        for (i, a) in tiles.iter().enumerate() {
+            for b in tiles.iter().skip(i + 2) {
+                assert!(a.is_disjoint(b), "route {:?} crosses itself", route);
+            }
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use bevy::utils::HashSet;
+
+use super::*;
+
+#[derive(Component)]
+pub struct AutopathGhost;
+
+#[derive(Default)]
+pub struct AutopathState {
+    picking: Option<TileIndex>,
+    facing_options: [bool; 8],
+    pub start: Option<TrackPos>,
+    pub end: Option<TrackPos>,
+    route: Option<Vec<TrackSegment>>,
+    pub status: Option<String>,
+}
+
+impl AutopathState {
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    fn plan(
+        &mut self,
+        network: &Network,
+        terrain: &TerrainMap,
+        world: &WorldConfig,
+        params: &TrackParams,
+    ) {
+        let (start, end) = match (self.start, self.end) {
+            (Some(start), Some(end)) => (start, end),
+            _ => return,
+        };
+
+        let occupied: HashSet<TileIndex> = network
+            .tracks
+            .values()
+            .filter(|track| track.segment.layer == params.layer)
+            .flat_map(|track| track.segment.tiles())
+            .collect();
+        let route = find_route(params, start, end, |tile| {
+            occupied.contains(&tile)
+                || !world.contains(tile)
+                || (params.layer != TrackLayer::Bridge
+                    && terrain.terrain_at(tile) == Terrain::Water)
+        });
+
+        self.status = match &route {
+            Some(_) => None,
+            None => Some("No route found.".to_string()),
+        };
+        self.route = route.map(|tracks| {
+            tracks
+                .windows(2)
+                .map(|pair| params.segment(pair[0], pair[1]))
+                .collect()
+        });
+    }
+}
+
+fn draw_facing(commands: &mut Commands, pos: TrackPos) {
+    let center = tile_to_center(pos.tile);
+    let mut path = PathBuilder::new();
+    path.move_to(center);
+    path.line_to(center + octant_to_unit(pos.facing) * TILE_SIZE);
+    commands
+        .spawn_bundle(build_path(path, Color::GRAY, 4., 0.1))
+        .insert(AutopathGhost);
+}
+
+pub fn autopath_tool(
+    mut commands: Commands,
+    mut state: ResMut<AutopathState>,
+    mut arrow_highlighter: Query<
+        (&mut Transform, &mut Visibility, &ArrowHighlighter),
+        Without<Arrow>,
+    >,
+    mut arrows: Query<(&mut Visibility, &mut DrawMode), With<Arrow>>,
+    mut events: EventWriter<TrackPlacementEvent>,
+
+    network: Res<Network>,
+    terrain: Res<TerrainMap>,
+    world: Res<WorldConfig>,
+    params: Res<TrackParams>,
+    mouse_pos: Res<MousePos>,
+    actions: Actions,
+    ghosts: Query<Entity, With<AutopathGhost>>,
+) {
+    let (mut arrows_tf, mut arrows_vis, arrow_highlighter) = arrow_highlighter.single_mut();
+
+    ghosts.for_each(|e| commands.entity(e).despawn());
+    if actions.just_pressed(Action::Remove) {
+        state.reset();
+        arrows_vis.is_visible = false;
+    }
+
+    for pos in [state.start, state.end].into_iter().flatten() {
+        draw_facing(&mut commands, pos);
+    }
+    if let Some(route) = &state.route {
+        let mut path = PathBuilder::new();
+        for segment in route.iter() {
+            track_path(&mut path, segment);
+        }
+        commands
+            .spawn_bundle(build_path(path, Color::GRAY, 4., 0.1))
+            .insert(AutopathGhost);
+    }
+
+    let mouse_pos = match mouse_pos.0 {
+        Some(pos) => pos,
+        None => return,
+    };
+    let mouse_tile = pos_to_tile(mouse_pos);
+    let clicked = actions.just_pressed(Action::Place);
+
+    let picking = state.picking;
+    match picking {
+        None if state.end.is_some() => {
+            if clicked {
+                if let Some(route) = state.route.take() {
+                    events.send(TrackPlacementEvent(route));
+                }
+                state.reset();
+            }
+        }
+        None => {
+            if clicked {
+                state.picking = Some(mouse_tile);
+                state.facing_options = network.get_connections(mouse_tile);
+                if state.facing_options.iter().all(|b| !b) {
+                    state.facing_options = [true; 8];
+                }
+
+                let tile_pos = tile_to_center(mouse_tile);
+                arrows_tf.translation.x = tile_pos.x;
+                arrows_tf.translation.y = tile_pos.y;
+            }
+        }
+        Some(tile) => {
+            let mouse_vec = mouse_pos - tile_to_center(tile);
+            let best = closest_facing(&state.facing_options, mouse_vec);
+            arrow_highlighter.highlight(&mut arrows, &state.facing_options, best);
+            arrows_vis.is_visible = true;
+
+            if clicked {
+                let pos = TrackPos::new(tile, best.into());
+                if state.start.is_none() {
+                    state.start = Some(pos);
+                } else {
+                    state.end = Some(pos);
+                    state.plan(
+                        network.as_ref(),
+                        terrain.as_ref(),
+                        world.as_ref(),
+                        params.as_ref(),
+                    );
+                }
+                state.picking = None;
+                arrows_vis.is_visible = false;
+            }
+        }
+    }
+}
+
+pub fn cleanup_autopath(
+    mut commands: Commands,
+    ghosts: Query<Entity, With<AutopathGhost>>,
+    mut state: ResMut<AutopathState>,
+    mut arrow_highlighter: Query<&mut Visibility, With<ArrowHighlighter>>,
+) {
+    arrow_highlighter.single_mut().is_visible = false;
+    ghosts.for_each(|g| commands.entity(g).despawn());
+    state.reset();
+}
@@ -0,0 +1,521 @@
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+use bevy::ecs::system::SystemParam;
+use serde::{Deserialize, Serialize};
+
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Action {
+    Place,
+    Remove,
+    Clear,
+    Pan,
+    // Held to change what other actions do, e.g. S-bends or self-driving trains
+    Alternate,
+    Command,
+    Forward,
+    Backward,
+    SteerLeft,
+    SteerRight,
+    CycleDriven,
+    Bridge,
+    Tunnel,
+    Waypoint,
+    Rotate,
+    Mirror,
+    Copy,
+    Paste,
+    FollowNext,
+    PanLeft,
+    PanRight,
+    PanUp,
+    PanDown,
+    Bookmark(usize),
+}
+
+impl Action {
+    pub fn name(&self) -> String {
+        match self {
+            Action::Place => "Place / select".to_string(),
+            Action::Remove => "Remove / cancel".to_string(),
+            Action::Clear => "Clear selection".to_string(),
+            Action::Pan => "Drag to pan".to_string(),
+            Action::Alternate => "Alternate".to_string(),
+            Action::Command => "Command".to_string(),
+            Action::Forward => "Drive forward".to_string(),
+            Action::Backward => "Drive backward".to_string(),
+            Action::SteerLeft => "Steer left".to_string(),
+            Action::SteerRight => "Steer right".to_string(),
+            Action::CycleDriven => "Next driven train".to_string(),
+            Action::Bridge => "Toggle bridge".to_string(),
+            Action::Tunnel => "Toggle tunnel".to_string(),
+            Action::Waypoint => "Add waypoint".to_string(),
+            Action::Rotate => "Rotate blueprint".to_string(),
+            Action::Mirror => "Mirror blueprint".to_string(),
+            Action::Copy => "Copy".to_string(),
+            Action::Paste => "Paste".to_string(),
+            Action::FollowNext => "Follow next train".to_string(),
+            Action::PanLeft => "Pan left".to_string(),
+            Action::PanRight => "Pan right".to_string(),
+            Action::PanUp => "Pan up".to_string(),
+            Action::PanDown => "Pan down".to_string(),
+            Action::Bookmark(index) => format!("Camera view {}", index + 1),
+        }
+    }
+}
+
+pub const ACTIONS: [Action; 32] = [
+    Action::Place,
+    Action::Remove,
+    Action::Clear,
+    Action::Pan,
+    Action::Alternate,
+    Action::Command,
+    Action::Forward,
+    Action::Backward,
+    Action::SteerLeft,
+    Action::SteerRight,
+    Action::CycleDriven,
+    Action::Bridge,
+    Action::Tunnel,
+    Action::Waypoint,
+    Action::Rotate,
+    Action::Mirror,
+    Action::Copy,
+    Action::Paste,
+    Action::FollowNext,
+    Action::PanLeft,
+    Action::PanRight,
+    Action::PanUp,
+    Action::PanDown,
+    Action::Bookmark(0),
+    Action::Bookmark(1),
+    Action::Bookmark(2),
+    Action::Bookmark(3),
+    Action::Bookmark(4),
+    Action::Bookmark(5),
+    Action::Bookmark(6),
+    Action::Bookmark(7),
+    Action::Bookmark(8),
+];
+
+// Keys that can be bound and saved, stored by their debug name
+const BINDABLE_KEYS: [KeyCode; 70] = [
+    KeyCode::A,
+    KeyCode::B,
+    KeyCode::C,
+    KeyCode::D,
+    KeyCode::E,
+    KeyCode::F,
+    KeyCode::G,
+    KeyCode::H,
+    KeyCode::I,
+    KeyCode::J,
+    KeyCode::K,
+    KeyCode::L,
+    KeyCode::M,
+    KeyCode::N,
+    KeyCode::O,
+    KeyCode::P,
+    KeyCode::Q,
+    KeyCode::R,
+    KeyCode::S,
+    KeyCode::T,
+    KeyCode::U,
+    KeyCode::V,
+    KeyCode::W,
+    KeyCode::X,
+    KeyCode::Y,
+    KeyCode::Z,
+    KeyCode::Key0,
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+    KeyCode::F1,
+    KeyCode::F2,
+    KeyCode::F3,
+    KeyCode::F4,
+    KeyCode::F5,
+    KeyCode::F6,
+    KeyCode::F7,
+    KeyCode::F8,
+    KeyCode::F9,
+    KeyCode::F10,
+    KeyCode::F11,
+    KeyCode::F12,
+    KeyCode::Left,
+    KeyCode::Right,
+    KeyCode::Up,
+    KeyCode::Down,
+    KeyCode::Space,
+    KeyCode::Escape,
+    KeyCode::Tab,
+    KeyCode::Return,
+    KeyCode::Back,
+    KeyCode::Delete,
+    KeyCode::Home,
+    KeyCode::End,
+    KeyCode::PageUp,
+    KeyCode::PageDown,
+    KeyCode::LShift,
+    KeyCode::RShift,
+    KeyCode::LControl,
+    KeyCode::RControl,
+    KeyCode::LAlt,
+    KeyCode::RAlt,
+    KeyCode::Comma,
+    KeyCode::Period,
+];
+
+const BINDABLE_BUTTONS: [MouseButton; 3] =
+    [MouseButton::Left, MouseButton::Right, MouseButton::Middle];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+}
+
+impl Binding {
+    pub fn name(&self) -> String {
+        match self {
+            Binding::Key(key) => format!("{:?}", key),
+            Binding::Mouse(button) => format!("Mouse{:?}", button),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        let key = BINDABLE_KEYS
+            .into_iter()
+            .map(Binding::Key)
+            .find(|binding| binding.name() == name);
+        key.or_else(|| {
+            BINDABLE_BUTTONS
+                .into_iter()
+                .map(Binding::Mouse)
+                .find(|binding| binding.name() == name)
+        })
+    }
+}
+
+pub struct Bindings(HashMap<Action, Vec<Binding>>);
+
+impl Default for Bindings {
+    fn default() -> Self {
+        use Binding::*;
+        let mut bindings = HashMap::from([
+            (Action::Place, vec![Mouse(MouseButton::Left)]),
+            (Action::Remove, vec![Mouse(MouseButton::Right)]),
+            (Action::Clear, vec![Key(KeyCode::Escape)]),
+            (Action::Pan, vec![Mouse(MouseButton::Middle)]),
+            (
+                Action::Alternate,
+                vec![Key(KeyCode::LShift), Key(KeyCode::RShift)],
+            ),
+            (
+                Action::Command,
+                vec![Key(KeyCode::LControl), Key(KeyCode::RControl)],
+            ),
+            (Action::Forward, vec![Key(KeyCode::W)]),
+            (Action::Backward, vec![Key(KeyCode::S)]),
+            (Action::SteerLeft, vec![Key(KeyCode::A)]),
+            (Action::SteerRight, vec![Key(KeyCode::D)]),
+            (Action::CycleDriven, vec![Key(KeyCode::Tab)]),
+            (Action::Bridge, vec![Key(KeyCode::B)]),
+            (Action::Tunnel, vec![Key(KeyCode::T)]),
+            (Action::Waypoint, vec![Key(KeyCode::Space)]),
+            (Action::Rotate, vec![Key(KeyCode::R)]),
+            (Action::Mirror, vec![Key(KeyCode::M)]),
+            (Action::Copy, vec![Key(KeyCode::C)]),
+            (Action::Paste, vec![Key(KeyCode::V)]),
+            (Action::FollowNext, vec![Key(KeyCode::F)]),
+            (Action::PanLeft, vec![Key(KeyCode::Left)]),
+            (Action::PanRight, vec![Key(KeyCode::Right)]),
+            (Action::PanUp, vec![Key(KeyCode::Up)]),
+            (Action::PanDown, vec![Key(KeyCode::Down)]),
+        ]);
+        let digits = [
+            KeyCode::Key1,
+            KeyCode::Key2,
+            KeyCode::Key3,
+            KeyCode::Key4,
+            KeyCode::Key5,
+            KeyCode::Key6,
+            KeyCode::Key7,
+            KeyCode::Key8,
+            KeyCode::Key9,
+        ];
+        for (index, key) in digits.into_iter().enumerate() {
+            bindings.insert(Action::Bookmark(index), vec![Key(key)]);
+        }
+        Self(bindings)
+    }
+}
+
+// On disk each binding is kept by name so KeyCode doesn't need to be serializable
+#[derive(Serialize, Deserialize)]
+struct SavedBindings(Vec<(Action, Vec<String>)>);
+
+impl Bindings {
+    pub fn get(&self, action: Action) -> &[Binding] {
+        self.0.get(&action).map_or(&[], |bindings| &bindings[..])
+    }
+
+    // Another action using the binding gives it up and takes this action's old bindings if
+    // that leaves it with none, so the two swap. Returns the action that gave it up
+    pub fn set(&mut self, action: Action, binding: Binding) -> Option<Action> {
+        let old = self.0.insert(action, vec![binding]).unwrap_or_default();
+        let other = ACTIONS
+            .into_iter()
+            .find(|other| *other != action && self.get(*other).contains(&binding))?;
+        let bindings = self.0.entry(other).or_default();
+        bindings.retain(|b| *b != binding);
+        if bindings.is_empty() {
+            bindings.extend(old.into_iter().filter(|b| *b != binding));
+        }
+        Some(other)
+    }
+
+    // Left and right modifier pairs read as one key, e.g. "Shift", and buttons as clicks
+    pub fn label(&self, action: Action) -> String {
+        let all: Vec<String> = self.get(action).iter().map(|b| b.name()).collect();
+        let paired = |side: char, other: char, name: &str| {
+            name.strip_prefix(side)
+                .filter(|rest| all.contains(&format!("{}{}", other, rest)))
+                .map(str::to_string)
+        };
+        let names: Vec<String> = all
+            .iter()
+            .filter(|name| paired('R', 'L', name).is_none())
+            .map(|name| paired('L', 'R', name).unwrap_or_else(|| name.clone()))
+            .map(|name| match name.strip_prefix("Mouse") {
+                Some(button) => format!("{} click", button),
+                None => name,
+            })
+            .collect();
+        if names.is_empty() {
+            "Unbound".to_string()
+        } else {
+            names.join(" / ")
+        }
+    }
+
+    // Saved bindings override the defaults, so newly added actions keep theirs
+    pub fn load() -> Self {
+        let mut bindings = Self::default();
+        let saved =
+            read_text(BINDINGS_FILE).and_then(|text| ron::from_str::<SavedBindings>(&text).ok());
+        for (action, names) in saved.map(|saved| saved.0).unwrap_or_default() {
+            let parsed: Vec<Binding> = names
+                .iter()
+                .filter_map(|name| Binding::from_name(name))
+                .collect();
+            bindings.0.insert(action, parsed);
+        }
+        bindings
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let saved = SavedBindings(
+            ACTIONS
+                .iter()
+                .map(|action| {
+                    let names = self.get(*action).iter().map(|b| b.name()).collect();
+                    (*action, names)
+                })
+                .collect(),
+        );
+        let text = ron::ser::to_string_pretty(&saved, ron::ser::PrettyConfig::new())
+            .map_err(|e| e.to_string())?;
+        write_text(BINDINGS_FILE, &text)
+    }
+}
+
+// Set while egui has the pointer, so clicks on windows don't reach the world
+#[derive(Default)]
+pub struct PointerOverUi(pub bool);
+
+pub fn update_pointer_over_ui(mut ctx: ResMut<EguiContext>, mut over_ui: ResMut<PointerOverUi>) {
+    let ctx = ctx.ctx_mut();
+    let owned = ctx.is_pointer_over_area() || ctx.wants_pointer_input();
+    if over_ui.0 != owned {
+        over_ui.0 = owned;
+    }
+}
+
+// Mouse bindings don't press or hold over the UI, releases still count so drags that began
+// in the world can finish on a window
+#[derive(SystemParam)]
+pub struct Actions<'w, 's> {
+    keys: Res<'w, Input<KeyCode>>,
+    mouse_buttons: Res<'w, Input<MouseButton>>,
+    bindings: Res<'w, Bindings>,
+    over_ui: Res<'w, PointerOverUi>,
+    #[system_param(ignore)]
+    marker: PhantomData<&'s ()>,
+}
+
+impl<'w, 's> Actions<'w, 's> {
+    fn any<K, M>(&self, action: Action, key: K, button: M) -> bool
+    where
+        K: Fn(&Input<KeyCode>, KeyCode) -> bool,
+        M: Fn(&Input<MouseButton>, MouseButton) -> bool,
+    {
+        self.bindings
+            .get(action)
+            .iter()
+            .any(|binding| match binding {
+                Binding::Key(code) => key(&*self.keys, *code),
+                Binding::Mouse(code) => button(&*self.mouse_buttons, *code),
+            })
+    }
+
+    pub fn pressed(&self, action: Action) -> bool {
+        let over_ui = self.over_ui.0;
+        self.any(action, |i, k| i.pressed(k), |i, b| !over_ui && i.pressed(b))
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        let over_ui = self.over_ui.0;
+        self.any(
+            action,
+            |i, k| i.just_pressed(k),
+            |i, b| !over_ui && i.just_pressed(b),
+        )
+    }
+
+    pub fn just_released(&self, action: Action) -> bool {
+        self.any(action, |i, k| i.just_released(k), |i, b| i.just_released(b))
+    }
+}
+
+#[derive(Default)]
+pub struct RebindState {
+    action: Option<Action>,
+    status: Option<String>,
+}
+
+// Takes the next bindable key or click outside the UI. Runs before anything reads input and
+// uses it up, so the press that completes a rebind doesn't also act. Escape cancels instead
+pub fn capture_rebind(
+    mut bindings: ResMut<Bindings>,
+    mut keys: ResMut<Input<KeyCode>>,
+    mut mouse_buttons: ResMut<Input<MouseButton>>,
+    over_ui: Res<PointerOverUi>,
+    mut rebind: ResMut<RebindState>,
+) {
+    let action = match rebind.action {
+        Some(action) => action,
+        None => return,
+    };
+    if keys.just_pressed(KeyCode::Escape) {
+        keys.clear_just_pressed(KeyCode::Escape);
+        rebind.action = None;
+        return;
+    }
+
+    let key = BINDABLE_KEYS
+        .into_iter()
+        .find(|key| keys.just_pressed(*key));
+    let button = BINDABLE_BUTTONS
+        .into_iter()
+        .find(|button| !over_ui.0 && mouse_buttons.just_pressed(*button));
+    let binding = match (key, button) {
+        (Some(key), _) => {
+            keys.clear_just_pressed(key);
+            Binding::Key(key)
+        }
+        // Buttons are reset outright, holding one would still count as pressed
+        (None, Some(button)) => {
+            mouse_buttons.reset(button);
+            Binding::Mouse(button)
+        }
+        (None, None) => return,
+    };
+
+    rebind.action = None;
+    let swapped = bindings.set(action, binding);
+    rebind.status = match bindings.save() {
+        Err(e) => Some(e),
+        Ok(()) => swapped.map(|other| {
+            format!(
+                "{} was on {}, it's now on {}.",
+                bindings.label(action),
+                other.name(),
+                bindings.label(other)
+            )
+        }),
+    };
+}
+
+pub fn bindings_ui(
+    mut ctx: ResMut<EguiContext>,
+    mut bindings: ResMut<Bindings>,
+    mut rebind: ResMut<RebindState>,
+) {
+    egui::Window::new("Key Bindings")
+        .collapsible(true)
+        .vscroll(true)
+        .show(ctx.ctx_mut(), |ui| {
+            egui::Grid::new("bindings").striped(true).show(ui, |ui| {
+                for action in ACTIONS {
+                    ui.label(action.name());
+                    if rebind.action == Some(action) {
+                        if ui.button("Press a key, Escape to cancel").clicked() {
+                            rebind.action = None;
+                        }
+                    } else if ui.button(bindings.label(action)).clicked() {
+                        rebind.action = Some(action);
+                    }
+                    ui.end_row();
+                }
+            });
+            ui.add_space(4.0);
+            if ui.button("Reset to defaults").clicked() {
+                *bindings = Bindings::default();
+                rebind.status = bindings.save().err();
+            }
+            if let Some(status) = &rebind.status {
+                ui.label(status);
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn taking_a_used_binding_swaps() {
+        let mut bindings = Bindings::default();
+        let swapped = bindings.set(Action::Forward, Binding::Key(KeyCode::S));
+        assert_eq!(swapped, Some(Action::Backward));
+        assert_eq!(bindings.get(Action::Forward), &[Binding::Key(KeyCode::S)]);
+        assert_eq!(bindings.get(Action::Backward), &[Binding::Key(KeyCode::W)]);
+    }
+
+    #[test]
+    fn unused_binding_leaves_others_alone() {
+        let mut bindings = Bindings::default();
+        assert_eq!(
+            bindings.set(Action::Forward, Binding::Key(KeyCode::I)),
+            None
+        );
+        assert_eq!(bindings.get(Action::Backward), &[Binding::Key(KeyCode::S)]);
+    }
+
+    #[test]
+    fn labels_pair_modifiers_and_name_clicks() {
+        let bindings = Bindings::default();
+        assert_eq!(bindings.label(Action::Alternate), "Shift");
+        assert_eq!(bindings.label(Action::Place), "Left click");
+    }
+}
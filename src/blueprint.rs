@@ -0,0 +1,264 @@
+use serde::{Deserialize, Serialize};
+
+use super::*;
+
+// Furthest a shared blueprint's tiles can be from its anchor
+const MAX_OFFSET: u32 = 10_000;
+
+// Track segments stored relative to an anchor tile
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Blueprint {
+    #[serde(default)]
+    pub name: String,
+    pub segments: Vec<TrackSegment>,
+}
+
+// Rotation in 45° steps, see rotate_tile_vec for how offsets stay on the grid
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct BlueprintOrientation {
+    pub eighths: i8,
+    pub mirrored: bool,
+}
+
+impl BlueprintOrientation {
+    pub fn rotate(&mut self, clockwise: bool) {
+        self.eighths = (self.eighths + if clockwise { 1 } else { 7 }) % 8;
+    }
+
+    pub fn degrees(&self) -> i32 {
+        self.eighths as i32 * 45
+    }
+
+    pub fn apply(&self, pos: TrackPos) -> TrackPos {
+        let (mut vec, mut facing) = (tile_to_vec(pos.tile), pos.facing);
+        if self.mirrored {
+            vec = mirror_tile_vec(vec);
+            facing = facing.mirror();
+        }
+        (
+            rotate_tile_vec(vec, self.eighths),
+            facing + Octant(self.eighths),
+        )
+            .into()
+    }
+}
+
+impl Blueprint {
+    pub fn copy(network: &Network, tracks: &[TrackID], anchor: TileIndex) -> Self {
+        let offset = -tile_to_vec(anchor);
+        let segments = tracks
+            .iter()
+            .filter_map(|id| network.get(*id))
+            .map(|track| {
+                track.segment.with_ends(
+                    track.segment.start.offset(offset),
+                    track.segment.end.offset(offset),
+                )
+            })
+            .collect();
+        Self {
+            name: String::new(),
+            segments,
+        }
+    }
+
+    // Compact single line form for sharing between sessions
+    pub fn to_share_string(&self) -> String {
+        ron::to_string(self).unwrap_or_default()
+    }
+
+    // Share strings come from anywhere, so check every segment before using it
+    pub fn from_share_string(text: &str) -> Result<Self, String> {
+        let mut blueprint: Self = ron::from_str(text.trim()).map_err(|e| e.to_string())?;
+        blueprint.validate()?;
+        for segment in &mut blueprint.segments {
+            *segment = segment.with_ends(segment.start, segment.end);
+        }
+        Ok(blueprint)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.segments.is_empty() {
+            return Err("it has no tracks".to_string());
+        }
+        for (index, segment) in self.segments.iter().enumerate() {
+            for end in [segment.start, segment.end] {
+                let (x, y) = end.tile;
+                if x.unsigned_abs() > MAX_OFFSET || y.unsigned_abs() > MAX_OFFSET {
+                    return Err(format!(
+                        "track {} reaches {:?}, too far from the anchor",
+                        index + 1,
+                        end.tile
+                    ));
+                }
+                if !(0..8).contains(&end.facing.0) {
+                    return Err(format!(
+                        "track {} has facing {}, expected 0 to 7",
+                        index + 1,
+                        end.facing.0
+                    ));
+                }
+            }
+            if segment.start.tile == segment.end.tile {
+                return Err(format!(
+                    "track {} starts and ends on {:?}",
+                    index + 1,
+                    segment.start.tile
+                ));
+            }
+            let length = segment.curve().approximate_length(0.1);
+            if !length.is_finite() {
+                return Err(format!("track {} has no usable curve", index + 1));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    pub fn place(&self, anchor: TileIndex, orientation: BlueprintOrientation) -> Vec<TrackSegment> {
+        let offset = tile_to_vec(anchor);
+        self.segments
+            .iter()
+            .map(|segment| {
+                segment.with_ends(
+                    orientation.apply(segment.start).offset(offset),
+                    orientation.apply(segment.end).offset(offset),
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pos(x: i32, y: i32, facing: i8) -> TrackPos {
+        TrackPos::new((x, y), Octant(facing))
+    }
+
+    fn share_string(segments: &str) -> String {
+        format!("(name:\"test\",segments:[{}])", segments)
+    }
+
+    #[test]
+    fn share_string_round_trips() {
+        let blueprint = Blueprint {
+            name: "Siding".to_string(),
+            segments: vec![TrackSegment::new(pos(0, 0, 1), pos(2, 2, 5))],
+        };
+        let parsed = Blueprint::from_share_string(&blueprint.to_share_string()).unwrap();
+        assert_eq!(parsed.name, "Siding");
+        assert_eq!(parsed.segments.len(), 1);
+        assert_eq!(parsed.segments[0].start, pos(0, 0, 1));
+        assert_eq!(parsed.segments[0].end, pos(2, 2, 5));
+    }
+
+    #[test]
+    fn share_string_rejects_bad_facings() {
+        for facing in [8, 127, -1] {
+            let text = share_string(&format!(
+                "(start:(tile:(0,0),facing:({})),end:(tile:(0,3),facing:(4)))",
+                facing
+            ));
+            let err = Blueprint::from_share_string(&text).unwrap_err();
+            assert!(err.contains("facing"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn share_string_rejects_degenerate_tracks() {
+        let text = share_string("(start:(tile:(1,1),facing:(0)),end:(tile:(1,1),facing:(4)))");
+        assert!(Blueprint::from_share_string(&text).is_err());
+
+        let text =
+            share_string("(start:(tile:(2147483647,0),facing:(0)),end:(tile:(0,3),facing:(4)))");
+        assert!(Blueprint::from_share_string(&text).is_err());
+
+        assert!(Blueprint::from_share_string(&share_string("")).is_err());
+        assert!(Blueprint::from_share_string("not a blueprint").is_err());
+    }
+
+    #[test]
+    fn quarter_turn_keeps_diagonals_on_the_grid() {
+        let orientation = BlueprintOrientation {
+            eighths: 2,
+            mirrored: false,
+        };
+        assert_eq!(orientation.apply(pos(2, 2, 1)), pos(2, -2, 3));
+        assert_eq!(orientation.apply(pos(0, 3, 0)), pos(3, 0, 2));
+    }
+
+    #[test]
+    fn eighth_turn_swaps_straights_and_diagonals() {
+        let orientation = BlueprintOrientation {
+            eighths: 1,
+            mirrored: false,
+        };
+        assert_eq!(orientation.apply(pos(0, 3, 0)), pos(3, 3, 1));
+        assert_eq!(orientation.apply(pos(3, 3, 1)), pos(3, 0, 2));
+        assert_eq!(orientation.apply(pos(-1, 2, 7)), pos(1, 2, 0));
+    }
+
+    #[test]
+    fn two_eighths_make_an_exact_quarter_turn() {
+        for x in -4..=4 {
+            for y in -4..=4 {
+                let vec = TileVec::new(x, y);
+                let twice = rotate_tile_vec(rotate_tile_vec(vec, 1), 1);
+                assert_eq!(twice, TileVec::new(y, -x));
+                assert_eq!(rotate_tile_vec(rotate_tile_vec(vec, 3), -3), vec);
+            }
+        }
+    }
+
+    #[test]
+    fn eight_turns_return_to_start() {
+        let mut orientation = BlueprintOrientation::default();
+        let start = pos(3, -1, 5);
+        for _ in 0..8 {
+            orientation.rotate(true);
+        }
+        assert_eq!(orientation, BlueprintOrientation::default());
+        orientation.rotate(false);
+        assert_eq!(orientation.eighths, 7);
+        assert_eq!(BlueprintOrientation::default().apply(start), start);
+    }
+
+    #[test]
+    fn mirror_flips_x_and_facing() {
+        let orientation = BlueprintOrientation {
+            eighths: 0,
+            mirrored: true,
+        };
+        assert_eq!(orientation.apply(pos(2, 1, 1)), pos(-2, 1, 7));
+    }
+
+    #[test]
+    fn placed_pieces_still_join() {
+        let blueprint = Blueprint {
+            name: String::new(),
+            segments: vec![
+                TrackSegment::new(pos(0, 0, 0), pos(0, 3, 4)),
+                TrackSegment::new(pos(0, 3, 0), pos(3, 6, 5)),
+            ],
+        };
+        for eighths in 0..8 {
+            for mirrored in [false, true] {
+                let orientation = BlueprintOrientation { eighths, mirrored };
+                let placed = blueprint.place((10, -4), orientation);
+                let ends = |segment: &TrackSegment| [segment.start, segment.end];
+                // The shared node faces opposite ways on the two pieces
+                let joined = ends(&placed[0]).into_iter().any(|a| {
+                    ends(&placed[1])
+                        .into_iter()
+                        .any(|b| a.tile == b.tile && a.facing == b.facing.inverse())
+                });
+                assert!(joined, "{:?} split the blueprint", orientation);
+            }
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use bevy_egui::egui::epaint::CubicBezierShape;
+
+use super::*;
+
+pub struct LibraryEntry {
+    path: String,
+    pub blueprint: Blueprint,
+}
+
+pub struct BlueprintLibrary {
+    dir: String,
+    pub blueprints: Vec<LibraryEntry>,
+    pub save_name: String,
+    pub import_text: String,
+    // Waiting on the user to confirm it can replace the saved one with the same name
+    pub pending_overwrite: Option<Blueprint>,
+    pub status: Option<String>,
+}
+
+// Anything outside [A-Za-z0-9-] is escaped as _XX so different names never share a file
+fn file_name(name: &str) -> String {
+    let mut file = String::new();
+    for byte in name.trim().bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'-' {
+            file.push(byte as char);
+        } else {
+            file.push_str(&format!("_{:02x}", byte));
+        }
+    }
+    format!("{}.ron", file)
+}
+
+impl BlueprintLibrary {
+    pub fn load(dir: &str) -> Self {
+        let mut blueprints = Vec::new();
+        for path in list_texts(dir) {
+            let blueprint = read_text(&path)
+                .ok_or_else(|| "couldn't read it".to_string())
+                .and_then(|text| ron::from_str::<Blueprint>(&text).map_err(|e| e.to_string()))
+                .and_then(|blueprint| blueprint.validate().map(|_| blueprint));
+            match blueprint {
+                Ok(blueprint) => blueprints.push(LibraryEntry { path, blueprint }),
+                Err(err) => warn!("Failed to load blueprint {:?}: {}", path, err),
+            }
+        }
+        blueprints.sort_by(|a, b| a.blueprint.name.cmp(&b.blueprint.name));
+
+        Self {
+            dir: dir.to_string(),
+            blueprints,
+            save_name: String::new(),
+            import_text: String::new(),
+            pending_overwrite: None,
+            status: None,
+        }
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        self.blueprints.iter().any(|e| e.blueprint.name == name)
+    }
+
+    // Asks before replacing a saved blueprint, call overwrite to go ahead
+    pub fn save(&mut self, blueprint: Blueprint) {
+        let path = format!("{}/{}", self.dir, file_name(&blueprint.name));
+        if self.contains(&blueprint.name) || exists(&path) {
+            self.status = Some(format!("\"{}\" already exists.", blueprint.name));
+            self.pending_overwrite = Some(blueprint);
+            return;
+        }
+        self.write(blueprint, path);
+    }
+
+    pub fn overwrite(&mut self) {
+        if let Some(blueprint) = self.pending_overwrite.take() {
+            let path = format!("{}/{}", self.dir, file_name(&blueprint.name));
+            self.write(blueprint, path);
+        }
+    }
+
+    fn write(&mut self, blueprint: Blueprint, path: String) {
+        let result = ron::ser::to_string_pretty(&blueprint, ron::ser::PrettyConfig::new())
+            .map_err(|e| e.to_string())
+            .and_then(|text| write_text(&path, &text));
+        if let Err(err) = result {
+            self.status = Some(format!("Failed to save: {}", err));
+            return;
+        }
+
+        // Drop the old file if the same name was saved under another path
+        for entry in &self.blueprints {
+            if entry.blueprint.name == blueprint.name && entry.path != path {
+                if let Err(err) = remove_text(&entry.path) {
+                    warn!("Failed to replace blueprint {:?}: {}", entry.path, err);
+                }
+            }
+        }
+        self.status = Some(format!("Saved \"{}\".", blueprint.name));
+        self.blueprints
+            .retain(|e| e.blueprint.name != blueprint.name && e.path != path);
+        self.blueprints.push(LibraryEntry { path, blueprint });
+        self.blueprints
+            .sort_by(|a, b| a.blueprint.name.cmp(&b.blueprint.name));
+    }
+
+    pub fn delete(&mut self, index: usize) {
+        let entry = self.blueprints.remove(index);
+        if let Err(err) = remove_text(&entry.path) {
+            warn!("Failed to delete blueprint {:?}: {}", entry.path, err);
+        }
+        self.status = Some(format!("Deleted \"{}\".", entry.blueprint.name));
+    }
+}
+
+pub fn setup_blueprint_library(mut commands: Commands) {
+    commands.insert_resource(BlueprintLibrary::load(BLUEPRINT_DIR));
+}
+
+fn draw_thumbnail(ui: &mut egui::Ui, blueprint: &Blueprint, size: f32) -> egui::Response {
+    let (response, painter) = ui.allocate_painter(egui::vec2(size, size), egui::Sense::click());
+    let rect = response.rect;
+    painter.rect_filled(rect, 2., egui::Color32::from_gray(42));
+
+    let curves: Vec<[Vec2; 4]> = blueprint
+        .segments
+        .iter()
+        .map(|segment| {
+            let (start, ctrl_one, ctrl_two, end) = segment.control_points();
+            [start, ctrl_one, ctrl_two, end]
+        })
+        .collect();
+    if curves.is_empty() {
+        return response;
+    }
+
+    let (min, max) = curves.iter().flatten().fold(
+        (Vec2::splat(f32::INFINITY), Vec2::splat(f32::NEG_INFINITY)),
+        |(min, max), point| (min.min(*point), max.max(*point)),
+    );
+    let center = (min + max) / 2.;
+    let scale = (size - 8.) / (max - min).max_element().max(TILE_SIZE);
+    let to_screen = |point: Vec2| {
+        let point = (point - center) * scale;
+        rect.center() + egui::vec2(point.x, -point.y)
+    };
+
+    for curve in curves {
+        painter.add(CubicBezierShape::from_points_stroke(
+            curve.map(to_screen),
+            false,
+            egui::Color32::TRANSPARENT,
+            egui::Stroke::new(2.0_f32, egui::Color32::WHITE),
+        ));
+    }
+
+    response
+}
+
+pub fn blueprint_library_ui(
+    mut ctx: ResMut<EguiContext>,
+    mut library: ResMut<BlueprintLibrary>,
+    mut state: ResMut<BlueprintState>,
+    network: Res<Network>,
+) {
+    egui::Window::new("Blueprint Library").show(ctx.ctx_mut(), |ui| {
+        ui.set_min_width(240.);
+
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut library.save_name);
+            let selection = state.selection(network.as_ref());
+            let can_save = selection.is_some() && !library.save_name.trim().is_empty();
+            if ui
+                .add_enabled(can_save, egui::Button::new("Save"))
+                .clicked()
+            {
+                if let Some(mut blueprint) = selection {
+                    blueprint.name = library.save_name.trim().to_string();
+                    library.save(blueprint);
+                    library.save_name.clear();
+                }
+            }
+        });
+        ui.label("Name a selection to save it.");
+        ui.add_space(4.0);
+
+        let mut delete = None;
+        egui::ScrollArea::vertical()
+            .max_height(320.)
+            .show(ui, |ui| {
+                for (index, LibraryEntry { blueprint, .. }) in library.blueprints.iter().enumerate()
+                {
+                    ui.horizontal(|ui| {
+                        if draw_thumbnail(ui, blueprint, 64.).clicked() {
+                            state.paste_blueprint(blueprint.clone());
+                        }
+                        ui.vertical(|ui| {
+                            ui.label(&blueprint.name);
+                            ui.horizontal(|ui| {
+                                if ui.small_button("Place").clicked() {
+                                    state.paste_blueprint(blueprint.clone());
+                                }
+                                if ui.small_button("Share").clicked() {
+                                    ui.output().copied_text = blueprint.to_share_string();
+                                }
+                                if ui.small_button("Delete").clicked() {
+                                    delete = Some(index);
+                                }
+                            });
+                        });
+                    });
+                }
+            });
+        if let Some(index) = delete {
+            library.delete(index);
+        }
+
+        ui.separator();
+        ui.label("Paste a shared blueprint:");
+        ui.text_edit_multiline(&mut library.import_text);
+        ui.horizontal(|ui| {
+            if ui.button("Import").clicked() {
+                match Blueprint::from_share_string(&library.import_text) {
+                    Ok(blueprint) => {
+                        library.import_text.clear();
+                        if !blueprint.name.is_empty() {
+                            library.save(blueprint.clone());
+                        }
+                        state.paste_blueprint(blueprint);
+                    }
+                    Err(err) => library.status = Some(format!("Invalid blueprint: {}", err)),
+                }
+            }
+            if ui
+                .add_enabled(
+                    state.clipboard.is_some(),
+                    egui::Button::new("Share clipboard"),
+                )
+                .clicked()
+            {
+                if let Some(blueprint) = &state.clipboard {
+                    ui.output().copied_text = blueprint.to_share_string();
+                }
+            }
+        });
+
+        if let Some(status) = &library.status {
+            ui.label(status);
+        }
+        if library.pending_overwrite.is_some() {
+            ui.horizontal(|ui| {
+                if ui.button("Overwrite").clicked() {
+                    library.overwrite();
+                }
+                if ui.button("Cancel").clicked() {
+                    library.pending_overwrite = None;
+                    library.status = None;
+                }
+            });
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_names_do_not_collide() {
+        assert_ne!(file_name("a b"), file_name("a_b"));
+        assert_ne!(file_name("a/b"), file_name("a_2fb"));
+        assert_eq!(file_name(" Loop-1 "), "Loop-1.ron");
+    }
+}
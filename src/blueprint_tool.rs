@@ -0,0 +1,182 @@
+use super::*;
+
+#[derive(Component)]
+pub struct BlueprintGhost;
+
+#[derive(Default)]
+pub struct BlueprintState {
+    selection_start: Option<TileIndex>,
+    anchor: Option<TileIndex>,
+    selected: Vec<TrackID>,
+    pub clipboard: Option<Blueprint>,
+    pub pasting: bool,
+    pub orientation: BlueprintOrientation,
+}
+
+impl BlueprintState {
+    pub fn has_selection(&self) -> bool {
+        !self.selected.is_empty()
+    }
+
+    pub fn selection(&self, network: &Network) -> Option<Blueprint> {
+        let anchor = self.anchor?;
+        let blueprint = Blueprint::copy(network, &self.selected, anchor);
+        if blueprint.is_empty() {
+            None
+        } else {
+            Some(blueprint)
+        }
+    }
+
+    pub fn copy(&mut self, network: &Network) {
+        if let Some(blueprint) = self.selection(network) {
+            self.clipboard = Some(blueprint);
+        }
+    }
+
+    pub fn paste(&mut self) {
+        self.pasting = self.clipboard.is_some();
+    }
+
+    pub fn paste_blueprint(&mut self, blueprint: Blueprint) {
+        self.clipboard = Some(blueprint);
+        self.paste();
+    }
+
+    fn clear_selection(&mut self) {
+        self.selection_start = None;
+        self.anchor = None;
+        self.selected.clear();
+    }
+}
+
+fn tracks_in_rect(network: &Network, a: TileIndex, b: TileIndex) -> Vec<TrackID> {
+    let min = (a.0.min(b.0), a.1.min(b.1));
+    let max = (a.0.max(b.0), a.1.max(b.1));
+    let inside =
+        |tile: TileIndex| tile.0 >= min.0 && tile.0 <= max.0 && tile.1 >= min.1 && tile.1 <= max.1;
+
+    network
+        .tracks
+        .iter()
+        .filter(|(_, track)| inside(track.start_tile()) && inside(track.end_tile()))
+        .map(|(id, _)| *id)
+        .collect()
+}
+
+pub fn blueprint_tool(
+    mut commands: Commands,
+    mut state: ResMut<BlueprintState>,
+    mut events: EventWriter<TrackPlacementEvent>,
+
+    network: Res<Network>,
+    mouse_pos: Res<MousePos>,
+    actions: Actions,
+    ghosts: Query<Entity, With<BlueprintGhost>>,
+) {
+    let ctrl = actions.pressed(Action::Command);
+    let shift = actions.pressed(Action::Alternate);
+
+    ghosts.for_each(|e| commands.entity(e).despawn());
+
+    if ctrl && actions.just_pressed(Action::Copy) {
+        state.copy(network.as_ref());
+    }
+    if ctrl && actions.just_pressed(Action::Paste) {
+        state.paste();
+    }
+
+    let mouse_tile = mouse_pos.0.map(pos_to_tile);
+
+    if state.pasting {
+        if actions.just_pressed(Action::Rotate) {
+            state.orientation.rotate(!shift);
+        }
+        if actions.just_pressed(Action::Mirror) {
+            state.orientation.mirrored = !state.orientation.mirrored;
+        }
+        if actions.just_pressed(Action::Clear) || actions.just_pressed(Action::Remove) {
+            state.pasting = false;
+            return;
+        }
+
+        let (mouse_tile, blueprint) = match (mouse_tile, &state.clipboard) {
+            (Some(tile), Some(blueprint)) => (tile, blueprint),
+            _ => return,
+        };
+
+        let segments = blueprint.place(mouse_tile, state.orientation);
+        let mut path = PathBuilder::new();
+        for segment in segments.iter() {
+            track_path(&mut path, segment);
+        }
+        commands
+            .spawn_bundle(build_path(path, Color::GRAY, 4., 0.1))
+            .insert(BlueprintGhost);
+
+        if actions.just_pressed(Action::Place) {
+            let batch: Vec<TrackSegment> = segments
+                .into_iter()
+                .filter(|segment| !network.contains_segment(segment))
+                .collect();
+            if !batch.is_empty() {
+                events.send(TrackPlacementEvent(batch));
+            }
+        }
+        return;
+    }
+
+    if actions.just_pressed(Action::Remove) || actions.just_pressed(Action::Clear) {
+        state.clear_selection();
+    }
+
+    if let Some(mouse_tile) = mouse_tile {
+        if actions.just_pressed(Action::Place) {
+            state.clear_selection();
+            state.selection_start = Some(mouse_tile);
+        }
+
+        if let Some(start) = state.selection_start {
+            state.anchor = Some(start);
+            state.selected = tracks_in_rect(network.as_ref(), start, mouse_tile);
+
+            let min = tile_to_vec(start).min(tile_to_vec(mouse_tile));
+            let max = tile_to_vec(start).max(tile_to_vec(mouse_tile));
+            let mut path = PathBuilder::new();
+            rect_path(
+                &mut path,
+                tile_vec_to_pos(min),
+                tile_vec_to_pos(max + IVec2::ONE),
+            );
+            commands
+                .spawn_bundle(build_path(path, Color::GRAY, 2., 0.1))
+                .insert(BlueprintGhost);
+
+            if !actions.pressed(Action::Place) {
+                state.selection_start = None;
+            }
+        }
+    }
+
+    if state.has_selection() {
+        let mut path = PathBuilder::new();
+        for id in state.selected.iter() {
+            if let Some(track) = network.get(*id) {
+                track_path(&mut path, &track.segment);
+            }
+        }
+        commands
+            .spawn_bundle(build_path(path, Color::YELLOW, 4., 15.))
+            .insert(BlueprintGhost);
+    }
+}
+
+pub fn cleanup_blueprint(
+    mut commands: Commands,
+    ghosts: Query<Entity, With<BlueprintGhost>>,
+    mut state: ResMut<BlueprintState>,
+) {
+    ghosts.for_each(|g| commands.entity(g).despawn());
+    state.selection_start = None;
+    state.pasting = false;
+}
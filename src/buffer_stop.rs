@@ -0,0 +1,57 @@
+use super::*;
+
+#[derive(Component)]
+pub struct BufferStopGhost;
+
+// What self-driving trains do on reaching a buffer stop
+pub struct EndOfLine {
+    pub reverse: bool,
+    pub pause: f32,
+}
+
+impl Default for EndOfLine {
+    fn default() -> Self {
+        Self {
+            reverse: true,
+            pause: 2.,
+        }
+    }
+}
+
+pub fn buffer_stop_tool(
+    mut commands: Commands,
+    mut network: ResMut<Network>,
+    mut render: EventWriter<NetworkRenderEvent>,
+    mouse_pos: Res<MousePos>,
+    actions: Actions,
+    ghosts: Query<Entity, With<BufferStopGhost>>,
+) {
+    ghosts.for_each(|e| commands.entity(e).despawn());
+    let mouse_tile = match mouse_pos.0 {
+        Some(pos) => pos_to_tile(pos),
+        None => return,
+    };
+
+    let dead_ends = network.dead_ends(mouse_tile);
+    for pos in dead_ends.iter() {
+        commands
+            .spawn_bundle(buffer_stop_shape(*pos, Color::GRAY))
+            .insert(BufferStopGhost);
+    }
+
+    if actions.just_pressed(Action::Place) && !dead_ends.is_empty() {
+        network.buffer_stops.extend(dead_ends);
+        render.send(NetworkRenderEvent);
+    }
+    if actions.just_pressed(Action::Remove) {
+        let before = network.buffer_stops.len();
+        network.buffer_stops.retain(|pos| pos.tile != mouse_tile);
+        if network.buffer_stops.len() != before {
+            render.send(NetworkRenderEvent);
+        }
+    }
+}
+
+pub fn cleanup_buffer_stops(mut commands: Commands, ghosts: Query<Entity, With<BufferStopGhost>>) {
+    ghosts.for_each(|g| commands.entity(g).despawn());
+}
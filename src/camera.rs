@@ -1,12 +1,73 @@
-use bevy::{
-    input::mouse::{MouseScrollUnit, MouseWheel},
-    prelude::*,
-};
+use bevy::input::mouse::{MouseScrollUnit, MouseWheel};
+
+use super::*;
+
+// How quickly the camera catches up with a followed train
+const FOLLOW_RATE: f32 = 5.;
+// How quickly smooth zoom eases towards the wheel's target
+const ZOOM_RATE: f32 = 12.;
+// Pixels from the window edge that start edge scrolling
+const EDGE_MARGIN: f32 = 16.;
+
+pub struct CameraSettings {
+    pub min_scale: f32,
+    pub max_scale: f32,
+    pub smooth_zoom: bool,
+    // Screen pixels per second
+    pub pan_speed: f32,
+    pub edge_scroll: bool,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        Self {
+            min_scale: 0.2,
+            max_scale: 10.,
+            smooth_zoom: true,
+            pan_speed: 800.,
+            edge_scroll: false,
+        }
+    }
+}
+
+// Saved camera positions and scales
+#[derive(Default)]
+pub struct CameraBookmarks(pub [Option<(Vec2, f32)>; 9]);
+
+// Scale the wheel is easing towards and the screen point it zooms around
+#[derive(Default)]
+pub struct ZoomTarget {
+    scale: Option<f32>,
+    anchor: Vec2,
+}
+
+// Train the camera is tracking, cleared by panning manually
+#[derive(Default)]
+pub struct CameraFollow(pub Option<Entity>);
+
+// Don't zoom out further than it takes to see the whole world
+fn clamp_scale(proj: &mut OrthographicProjection, world: &WorldConfig) {
+    let (min, max) = world.rect();
+    let view = Vec2::new(proj.right - proj.left, proj.top - proj.bottom);
+    proj.scale = proj.scale.min(((max - min) / view).max_element());
+}
+
+// Keep the centre of the view inside the world
+pub fn clamp_center(transform: &mut Transform, world: &WorldConfig) {
+    let (min, max) = world.rect();
+    let center = transform.translation.truncate().clamp(min, max);
+    transform.translation = center.extend(transform.translation.z);
+}
 
 pub fn camera_zoom(
     mut cameras: Query<(&mut OrthographicProjection, &mut Transform), With<Camera>>,
     mut scroll_events: EventReader<MouseWheel>,
+    over_ui: Res<PointerOverUi>,
     windows: Res<Windows>,
+    world: Res<WorldConfig>,
+    settings: Res<CameraSettings>,
+    time: Res<Time>,
+    mut target: Local<ZoomTarget>,
 ) {
     let pixels_per_line = 100.; // Maybe make configurable?
     let scroll = scroll_events
@@ -16,38 +77,148 @@ pub fn camera_zoom(
             MouseScrollUnit::Line => ev.y * pixels_per_line,
         })
         .sum::<f32>();
-
-    if scroll == 0. {
-        return;
-    }
+    // Scrolling a window shouldn't zoom the world behind it
+    let scroll = if over_ui.0 { 0. } else { scroll };
 
     let window = windows.get_primary().unwrap();
-    let window_size = Vec2::new(window.width(), window.height());
-    let mouse_normalized_screen_pos =
-        (window.cursor_position().unwrap() / window_size) * 2. - Vec2::ONE;
-
     for (mut proj, mut pos) in &mut cameras {
-        let old_scale = proj.scale;
-        proj.scale *= 1. + -scroll * 0.001;
-        // proj.scale = proj.scale * (1. + -scroll * 0.001).max(cam.min_scale);
+        if scroll != 0. {
+            // Zoom around the cursor, or the centre when it's outside the window
+            let window_size = Vec2::new(window.width(), window.height());
+            target.anchor = window
+                .cursor_position()
+                .map_or(Vec2::ZERO, |cursor| (cursor / window_size) * 2. - Vec2::ONE);
+            let scale = target.scale.unwrap_or(proj.scale) * (1. + -scroll * 0.001);
+            target.scale = Some(scale.clamp(settings.min_scale, settings.max_scale));
+        }
+        let goal = match target.scale {
+            Some(goal) => goal,
+            None => continue,
+        };
 
-        // if let Some(max_scale) = cam.max_scale {
-        //     proj.scale = proj.scale.min(max_scale);
-        // }
+        let old_scale = proj.scale;
+        proj.scale = if settings.smooth_zoom {
+            let t = 1. - (-ZOOM_RATE * time.delta_seconds()).exp();
+            old_scale + (goal - old_scale) * t
+        } else {
+            goal
+        };
+        clamp_scale(&mut proj, &world);
+        // Stop once there, or when the world bounds won't let it get any further
+        if (proj.scale - goal).abs() < goal * 0.001 || proj.scale == old_scale {
+            target.scale = None;
+        }
 
         let proj_size = Vec2::new(proj.right, proj.top);
-        let mouse_world_pos =
-            pos.translation.truncate() + mouse_normalized_screen_pos * proj_size * old_scale;
-        pos.translation = (mouse_world_pos - mouse_normalized_screen_pos * proj_size * proj.scale)
-            .extend(pos.translation.z);
+        let mouse_world_pos = pos.translation.truncate() + target.anchor * proj_size * old_scale;
+        pos.translation =
+            (mouse_world_pos - target.anchor * proj_size * proj.scale).extend(pos.translation.z);
+        clamp_center(&mut pos, &world);
+    }
+}
+
+// Arrow keys and the window edges pan, scaled so the speed feels the same at any zoom
+pub fn camera_keys(
+    mut ctx: ResMut<EguiContext>,
+    time: Res<Time>,
+    actions: Actions,
+    windows: Res<Windows>,
+    settings: Res<CameraSettings>,
+    world: Res<WorldConfig>,
+    mut follow: ResMut<CameraFollow>,
+    mut cameras: Query<(&mut Transform, &OrthographicProjection), With<Camera>>,
+) {
+    let ctx = ctx.ctx_mut();
+    let mut dir = Vec2::ZERO;
+    if !ctx.wants_keyboard_input() {
+        for (action, offset) in [
+            (Action::PanLeft, Vec2::NEG_X),
+            (Action::PanRight, Vec2::X),
+            (Action::PanUp, Vec2::Y),
+            (Action::PanDown, Vec2::NEG_Y),
+        ] {
+            if actions.pressed(action) {
+                dir += offset;
+            }
+        }
+    }
+    if settings.edge_scroll && !ctx.is_pointer_over_area() {
+        let window = windows.get_primary().unwrap();
+        if let Some(cursor) = window.cursor_position() {
+            let size = Vec2::new(window.width(), window.height());
+            dir += Vec2::select(
+                cursor.cmplt(Vec2::splat(EDGE_MARGIN)),
+                -Vec2::ONE,
+                Vec2::ZERO,
+            );
+            dir += Vec2::select(cursor.cmpgt(size - EDGE_MARGIN), Vec2::ONE, Vec2::ZERO);
+        }
+    }
+    if dir == Vec2::ZERO {
+        return;
+    }
+
+    follow.0 = None;
+    for (mut transform, proj) in &mut cameras {
+        let step = dir.normalize() * settings.pan_speed * proj.scale * time.delta_seconds();
+        transform.translation += step.extend(0.);
+        clamp_center(&mut transform, &world);
+    }
+}
+
+// Command plus a bookmark saves the view, the bookmark alone jumps back to it
+pub fn camera_bookmarks(
+    mut ctx: ResMut<EguiContext>,
+    actions: Actions,
+    world: Res<WorldConfig>,
+    mut bookmarks: ResMut<CameraBookmarks>,
+    mut follow: ResMut<CameraFollow>,
+    mut cameras: Query<(&mut Transform, &mut OrthographicProjection), With<Camera>>,
+) {
+    if ctx.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+    let ctrl = actions.pressed(Action::Command);
+    for index in 0..bookmarks.0.len() {
+        if !actions.just_pressed(Action::Bookmark(index)) {
+            continue;
+        }
+        for (mut transform, mut proj) in &mut cameras {
+            if ctrl {
+                bookmarks.0[index] = Some((transform.translation.truncate(), proj.scale));
+            } else if let Some((pos, scale)) = bookmarks.0[index] {
+                transform.translation = pos.extend(transform.translation.z);
+                proj.scale = scale;
+                // The world may have shrunk since the view was saved
+                clamp_scale(&mut proj, &world);
+                clamp_center(&mut transform, &world);
+                follow.0 = None;
+            }
+        }
+    }
+}
+
+// Pull the view back inside when the world is resized
+pub fn clamp_camera(
+    world: Res<WorldConfig>,
+    mut cameras: Query<(&mut Transform, &mut OrthographicProjection), With<Camera>>,
+) {
+    if !world.is_changed() {
+        return;
+    }
+    for (mut transform, mut proj) in &mut cameras {
+        clamp_scale(&mut proj, &world);
+        clamp_center(&mut transform, &world);
     }
 }
 
 pub fn camera_pan(
     windows: Res<Windows>,
-    mouse_buttons: Res<Input<MouseButton>>,
+    actions: Actions,
     mut cameras: Query<(&mut Transform, &OrthographicProjection), With<Camera>>,
     mut last_pos: Local<Option<Vec2>>,
+    world: Res<WorldConfig>,
+    mut follow: ResMut<CameraFollow>,
 ) {
     let window = windows.get_primary().unwrap();
 
@@ -59,14 +230,115 @@ pub fn camera_pan(
     let delta = current_pos - last_pos.unwrap_or(current_pos);
 
     for (mut transform, projection) in &mut cameras {
-        if mouse_buttons.pressed(MouseButton::Middle) {
+        if actions.pressed(Action::Pan) {
+            if delta != Vec2::ZERO && follow.0.is_some() {
+                follow.0 = None;
+            }
             let scaling = Vec2::new(
                 window.width() / (projection.right - projection.left),
                 window.height() / (projection.top - projection.bottom),
             ) * projection.scale;
 
             transform.translation -= (delta * scaling).extend(0.);
+            clamp_center(&mut transform, &world);
         }
     }
     *last_pos = Some(current_pos);
 }
+
+pub fn follow_camera(
+    time: Res<Time>,
+    world: Res<WorldConfig>,
+    mut follow: ResMut<CameraFollow>,
+    mut cameras: Query<&mut Transform, With<Camera>>,
+    trains: Query<&Transform, (With<Train>, Without<Camera>)>,
+) {
+    let target = match follow.0 {
+        Some(entity) => match trains.get(entity) {
+            Ok(tf) => tf.translation.truncate(),
+            Err(_) => {
+                follow.0 = None;
+                return;
+            }
+        },
+        None => return,
+    };
+
+    let t = 1. - (-FOLLOW_RATE * time.delta_seconds()).exp();
+    for mut transform in &mut cameras {
+        let center = transform.translation.truncate().lerp(target, t);
+        transform.translation = center.extend(transform.translation.z);
+        clamp_center(&mut transform, &world);
+    }
+}
+
+// Steps through the trains in entity order
+pub fn cycle_follow(
+    mut ctx: ResMut<EguiContext>,
+    actions: Actions,
+    mut follow: ResMut<CameraFollow>,
+    trains: Query<Entity, With<Train>>,
+) {
+    if !actions.just_pressed(Action::FollowNext) || ctx.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+    follow.0 = next_entity(follow.0, trains.iter());
+}
+
+pub fn camera_ui(
+    mut ctx: ResMut<EguiContext>,
+    mut follow: ResMut<CameraFollow>,
+    mut settings: ResMut<CameraSettings>,
+    bookmarks: Res<CameraBookmarks>,
+    bindings: Res<Bindings>,
+    trains: Query<&Train>,
+) {
+    let follow_key = bindings.label(Action::FollowNext);
+    egui::Window::new("Camera").show(ctx.ctx_mut(), |ui| {
+        match follow.0.and_then(|e| trains.get(e).ok()) {
+            Some(train) => {
+                ui.label(format!(
+                    "Following {} at {:.0}",
+                    train.kind.name,
+                    train.speed()
+                ));
+                ui.label(format!("{} for the next train, pan to stop.", follow_key));
+                if ui.button("Stop following").clicked() {
+                    follow.0 = None;
+                }
+            }
+            None => {
+                ui.label(format!("{} to follow a train.", follow_key));
+            }
+        }
+        ui.label(format!(
+            "{}, {}, {}, {} to pan.",
+            bindings.label(Action::PanUp),
+            bindings.label(Action::PanLeft),
+            bindings.label(Action::PanDown),
+            bindings.label(Action::PanRight)
+        ));
+        ui.label(format!(
+            "{} with a camera view key saves it, the key alone returns to it.",
+            bindings.label(Action::Command)
+        ));
+        let saved: Vec<String> = bookmarks
+            .0
+            .iter()
+            .enumerate()
+            .filter(|(_, bookmark)| bookmark.is_some())
+            .map(|(index, _)| (index + 1).to_string())
+            .collect();
+        if !saved.is_empty() {
+            ui.label(format!("Saved views: {}", saved.join(", ")));
+        }
+
+        egui::CollapsingHeader::new("Settings").show(ui, |ui| {
+            ui.add(egui::Slider::new(&mut settings.min_scale, 0.05..=1.0).text("Closest zoom"));
+            ui.add(egui::Slider::new(&mut settings.max_scale, 1.0..=50.0).text("Furthest zoom"));
+            ui.add(egui::Slider::new(&mut settings.pan_speed, 100.0..=3000.0).text("Pan speed"));
+            ui.checkbox(&mut settings.smooth_zoom, "Smooth zoom");
+            ui.checkbox(&mut settings.edge_scroll, "Edge scrolling");
+        });
+    });
+}
@@ -1,3 +1,7 @@
 pub const WINDOW_HEIGHT: f32 = 900.;
 pub const WINDOW_WIDTH: f32 = 1600.;
 pub const TILE_SIZE: f32 = 40.;
+pub const BLUEPRINT_DIR: &str = "blueprints";
+pub const BINDINGS_FILE: &str = "bindings.ron";
+pub const MAX_TRAIN_SPEED: f32 = 300.;
+pub const MAX_LATERAL_ACC: f32 = 150.;
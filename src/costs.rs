@@ -0,0 +1,224 @@
+use super::*;
+
+pub const STARTING_FUNDS: f32 = 20_000.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GameMode {
+    // Building is free
+    #[default]
+    Sandbox,
+    // Building costs money and is blocked when it can't be afforded
+    Budget,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CostModel {
+    pub per_tile: f32,
+    // Added per tile of track that curves
+    pub curve_per_tile: f32,
+    pub bridge_multiplier: f32,
+    pub tunnel_multiplier: f32,
+    // Per end that branches off existing track
+    pub junction: f32,
+    // Share of the cost given back on removal
+    pub refund: f32,
+}
+
+impl Default for CostModel {
+    fn default() -> Self {
+        Self {
+            per_tile: 10.,
+            curve_per_tile: 5.,
+            bridge_multiplier: 4.,
+            tunnel_multiplier: 6.,
+            junction: 50.,
+            refund: 0.5,
+        }
+    }
+}
+
+impl CostModel {
+    pub fn track_cost(&self, track: &TrackData) -> f32 {
+        let tiles = track.length / TILE_SIZE;
+        let mut cost = tiles * self.per_tile;
+        if track.max_curvature > 0. {
+            cost += tiles * self.curve_per_tile;
+        }
+        cost * match track.segment.layer {
+            TrackLayer::Tunnel => self.tunnel_multiplier,
+            TrackLayer::Ground => 1.,
+            TrackLayer::Bridge => self.bridge_multiplier,
+        }
+    }
+
+    // Tracks already in the network or earlier in the batch are free and won't be laid again.
+    // Extending a dead end needs no switch, so only ends that branch off track in the network
+    // or earlier in the batch pay for a junction
+    pub fn segment_cost(
+        &self,
+        network: &Network,
+        terrain: &TerrainMap,
+        earlier: &[TrackSegment],
+        segment: &TrackSegment,
+    ) -> f32 {
+        let same = |other: &TrackSegment| other.start == segment.start && other.end == segment.end;
+        if network.contains_segment(segment) || earlier.iter().any(same) {
+            return 0.;
+        }
+        let junctions = [segment.start, segment.end]
+            .into_iter()
+            .filter(|pos| {
+                network.has_end(*pos)
+                    || earlier
+                        .iter()
+                        .any(|other| other.start == *pos || other.end == *pos)
+            })
+            .count();
+        self.track_cost(&TrackData::from(*segment)) * terrain.cost_multiplier(segment)
+            + junctions as f32 * self.junction
+    }
+
+    // What each segment of the batch costs, in order
+    pub fn segment_costs(
+        &self,
+        network: &Network,
+        terrain: &TerrainMap,
+        segments: &[TrackSegment],
+    ) -> Vec<f32> {
+        segments
+            .iter()
+            .enumerate()
+            .map(|(i, segment)| self.segment_cost(network, terrain, &segments[..i], segment))
+            .collect()
+    }
+
+    pub fn batch_cost(
+        &self,
+        network: &Network,
+        terrain: &TerrainMap,
+        segments: &[TrackSegment],
+    ) -> f32 {
+        self.segment_costs(network, terrain, segments).iter().sum()
+    }
+
+    // Based on what was paid, so free track gives nothing back
+    pub fn refund(&self, track: &TrackData) -> f32 {
+        track.paid * self.refund
+    }
+}
+
+pub fn budget_ui(
+    mut ctx: ResMut<EguiContext>,
+    mut mode: ResMut<GameMode>,
+    mut costs: ResMut<CostModel>,
+    finances: Res<Finances>,
+) {
+    egui::Window::new("Budget").show(ctx.ctx_mut(), |ui| {
+        let mut selected = *mode;
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut selected, GameMode::Sandbox, "Sandbox");
+            ui.selectable_value(&mut selected, GameMode::Budget, "Budget");
+        });
+        if selected != *mode {
+            *mode = selected;
+        }
+        ui.label(format!("Balance: ${:.0}", finances.balance));
+        ui.label(format!("Income: ${:.0}", finances.income));
+        ui.label(format!("Running costs: ${:.0}", finances.costs));
+        ui.label(format!("Construction: ${:.0}", finances.construction));
+        if *mode == GameMode::Budget {
+            ui.add_space(4.0);
+            ui.label("Removing track refunds part of its cost.");
+            cost_model_ui(ui, &mut costs);
+        }
+    });
+}
+
+fn cost_model_ui(ui: &mut egui::Ui, costs: &mut CostModel) {
+    egui::CollapsingHeader::new("Costs").show(ui, |ui| {
+        ui.add(egui::Slider::new(&mut costs.per_tile, 0.0..=100.0).text("Per tile"));
+        ui.add(egui::Slider::new(&mut costs.curve_per_tile, 0.0..=100.0).text("Curves"));
+        ui.add(egui::Slider::new(&mut costs.bridge_multiplier, 1.0..=10.0).text("Bridges ×"));
+        ui.add(egui::Slider::new(&mut costs.tunnel_multiplier, 1.0..=10.0).text("Tunnels ×"));
+        ui.add(egui::Slider::new(&mut costs.junction, 0.0..=500.0).text("Junctions"));
+        ui.add(egui::Slider::new(&mut costs.refund, 0.0..=1.0).text("Refund"));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn straight(from: TileIndex, to: TileIndex) -> TrackSegment {
+        TrackSegment::new(TrackPos::new(from, Octant(0)), TrackPos::new(to, Octant(4)))
+    }
+
+    fn base_cost(costs: &CostModel, terrain: &TerrainMap, segment: &TrackSegment) -> f32 {
+        costs.track_cost(&TrackData::from(*segment)) * terrain.cost_multiplier(segment)
+    }
+
+    #[test]
+    fn existing_track_is_free() {
+        let (costs, terrain) = (CostModel::default(), TerrainMap::default());
+        let mut network = Network::default();
+        let segment = straight((0, 0), (0, 3));
+        network.add_track(segment);
+        assert_eq!(costs.segment_cost(&network, &terrain, &[], &segment), 0.);
+    }
+
+    #[test]
+    fn extending_a_dead_end_is_not_a_junction() {
+        let (costs, terrain) = (CostModel::default(), TerrainMap::default());
+        let mut network = Network::default();
+        network.add_track(straight((0, 0), (0, 3)));
+        let next = straight((0, 3), (0, 6));
+        assert_eq!(
+            costs.segment_cost(&network, &terrain, &[], &next),
+            base_cost(&costs, &terrain, &next)
+        );
+    }
+
+    #[test]
+    fn branching_pays_for_a_junction() {
+        let (costs, terrain) = (CostModel::default(), TerrainMap::default());
+        let mut network = Network::default();
+        network.add_track(straight((0, 0), (0, 3)));
+        let branch = TrackSegment::new(
+            TrackPos::new((0, 0), Octant(0)),
+            TrackPos::new((2, 5), Octant(5)),
+        );
+        assert_eq!(
+            costs.segment_cost(&network, &terrain, &[], &branch),
+            base_cost(&costs, &terrain, &branch) + costs.junction
+        );
+    }
+
+    #[test]
+    fn branches_within_a_batch_pay_for_a_junction() {
+        let (costs, terrain) = (CostModel::default(), TerrainMap::default());
+        let network = Network::default();
+        let main = straight((0, 0), (0, 3));
+        let branch = TrackSegment::new(
+            TrackPos::new((0, 0), Octant(0)),
+            TrackPos::new((2, 5), Octant(5)),
+        );
+        let paid = costs.segment_costs(&network, &terrain, &[main, branch, main]);
+        assert_eq!(paid[0], base_cost(&costs, &terrain, &main));
+        assert_eq!(
+            paid[1],
+            base_cost(&costs, &terrain, &branch) + costs.junction
+        );
+        assert_eq!(paid[2], 0.);
+    }
+
+    #[test]
+    fn refund_is_a_share_of_what_was_paid() {
+        let mut costs = CostModel::default();
+        let mut track = TrackData::from(straight((0, 0), (0, 3)));
+        assert_eq!(costs.refund(&track), 0.);
+
+        track.paid = 100.;
+        costs.per_tile *= 10.;
+        assert_eq!(costs.refund(&track), 100. * costs.refund);
+    }
+}
@@ -0,0 +1,197 @@
+use bevy_prototype_lyon::prelude::tess::geom::{CubicBezierSegment, Point, Vector};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::*;
+
+pub type CrossingID = usize;
+static NEXT_CROSSING_ID: AtomicUsize = AtomicUsize::new(0);
+
+// Radius around a crossing that only one train may occupy at a time
+pub const CROSSING_CLEARANCE: f32 = TILE_SIZE;
+// Coarse samples taken before narrowing in on the nearest point of a curve
+const NEAREST_SAMPLES: usize = 32;
+
+// Two tracks that intersect without sharing a node
+#[derive(Debug, Clone, Copy)]
+pub struct Crossing {
+    pub tracks: (TrackID, TrackID),
+    pub layer: TrackLayer,
+    pub pos: Vec2,
+    pub tangents: (Vec2, Vec2),
+}
+
+impl Crossing {
+    pub fn next_id() -> CrossingID {
+        NEXT_CROSSING_ID.fetch_add(1, Ordering::SeqCst)
+    }
+
+    pub fn has_track(&self, id: TrackID) -> bool {
+        self.tracks.0 == id || self.tracks.1 == id
+    }
+}
+
+fn to_vec(point: Point<f32>) -> Vec2 {
+    Vec2::new(point.x, point.y)
+}
+
+fn to_dir(vector: Vector<f32>) -> Vec2 {
+    Vec2::new(vector.x, vector.y).normalize_or_zero()
+}
+
+// Parameter of the point on a curve closest to pos, sampled then narrowed down
+fn nearest_t(curve: &CubicBezierSegment<f32>, pos: Vec2) -> f32 {
+    let distance = |t: f32| to_vec(curve.sample(t)).distance_squared(pos);
+    let mut step = 1. / NEAREST_SAMPLES as f32;
+    let mut best = 0.;
+    for i in 1..=NEAREST_SAMPLES {
+        let t = i as f32 * step;
+        if distance(t) < distance(best) {
+            best = t;
+        }
+    }
+    while step > 1e-4 {
+        step /= 2.;
+        for t in [best - step, best + step] {
+            let t = t.clamp(0., 1.);
+            if distance(t) < distance(best) {
+                best = t;
+            }
+        }
+    }
+    best
+}
+
+// Tracks meeting where both end form a junction instead, a track ending on another's
+// body still crosses it
+pub fn find_crossings(
+    layer: TrackLayer,
+    (a, a_curve): (TrackID, &CubicBezierSegment<f32>),
+    (b, b_curve): (TrackID, &CubicBezierSegment<f32>),
+) -> Vec<Crossing> {
+    let a_ends = [a_curve.from, a_curve.to].map(to_vec);
+    let b_ends = [b_curve.from, b_curve.to].map(to_vec);
+    let at_end = |ends: &[Vec2; 2], pos: Vec2| ends.iter().any(|end| end.distance(pos) < 1.);
+    // Ends resting on the other track are easy for the intersection test to miss
+    let mut hits: Vec<(f32, f32)> = a_curve.cubic_intersections_t(b_curve).to_vec();
+    for (a_t, pos) in [0., 1.].into_iter().zip(a_ends) {
+        let b_t = nearest_t(b_curve, pos);
+        if to_vec(b_curve.sample(b_t)).distance(pos) < 1. {
+            hits.push((a_t, b_t));
+        }
+    }
+    for (b_t, pos) in [0., 1.].into_iter().zip(b_ends) {
+        let a_t = nearest_t(a_curve, pos);
+        if to_vec(a_curve.sample(a_t)).distance(pos) < 1. {
+            hits.push((a_t, b_t));
+        }
+    }
+    let mut crossings: Vec<Crossing> = Vec::new();
+    for (a_t, b_t) in hits {
+        let pos = to_vec(a_curve.sample(a_t));
+        if at_end(&a_ends, pos) && at_end(&b_ends, pos) {
+            continue;
+        }
+        if crossings.iter().any(|c| c.pos.distance(pos) < 1.) {
+            continue;
+        }
+        crossings.push(Crossing {
+            tracks: (a, b),
+            layer,
+            pos,
+            tangents: (
+                to_dir(a_curve.derivative(a_t)),
+                to_dir(b_curve.derivative(b_t)),
+            ),
+        });
+    }
+    crossings
+}
+
+// Which train currently holds each crossing
+#[derive(Default)]
+pub struct CrossingOccupancy(HashMap<CrossingID, Entity>);
+
+impl CrossingOccupancy {
+    // Claims the crossings on the train's track around pos, fails if another train holds one
+    pub fn try_enter(
+        &mut self,
+        network: &Network,
+        train: Entity,
+        track: TrackID,
+        pos: Vec2,
+        layer: TrackLayer,
+    ) -> bool {
+        let inside: Vec<CrossingID> = network
+            .crossings_near(pos, CROSSING_CLEARANCE, layer)
+            .into_iter()
+            .filter(|id| network.crossings[id].has_track(track))
+            .collect();
+        if inside
+            .iter()
+            .any(|id| self.0.get(id).map_or(false, |holder| *holder != train))
+        {
+            return false;
+        }
+
+        self.0.retain(|_, holder| *holder != train);
+        for id in inside {
+            self.0.insert(id, train);
+        }
+        true
+    }
+}
+
+pub fn release_crossings(
+    network: Res<Network>,
+    trains: Query<(), With<Train>>,
+    mut occupancy: ResMut<CrossingOccupancy>,
+) {
+    occupancy
+        .0
+        .retain(|id, holder| network.crossings.contains_key(id) && trains.get(*holder).is_ok());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn curve(
+        start: TileIndex,
+        start_facing: i8,
+        end: TileIndex,
+        end_facing: i8,
+    ) -> CubicBezierSegment<f32> {
+        TrackSegment::new(
+            TrackPos::new(start, Octant(start_facing)),
+            TrackPos::new(end, Octant(end_facing)),
+        )
+        .curve()
+    }
+
+    #[test]
+    fn straights_cross_between_nodes() {
+        let a = curve((0, -3), 0, (0, 3), 4);
+        let b = curve((-3, 0), 2, (3, 0), 6);
+        let crossings = find_crossings(TrackLayer::Ground, (0, &a), (1, &b));
+        assert_eq!(crossings.len(), 1);
+        assert!(crossings[0].pos.distance(tile_to_center((0, 0))) < 1.);
+    }
+
+    #[test]
+    fn track_ending_on_another_still_crosses() {
+        let a = curve((0, -3), 0, (0, 0), 4);
+        let b = curve((-3, 0), 2, (3, 0), 6);
+        assert_eq!(
+            find_crossings(TrackLayer::Ground, (0, &a), (1, &b)).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn shared_node_is_a_junction() {
+        let a = curve((0, -3), 0, (0, 0), 4);
+        let b = curve((0, 0), 2, (3, 0), 6);
+        assert!(find_crossings(TrackLayer::Ground, (0, &a), (1, &b)).is_empty());
+    }
+}
@@ -0,0 +1,232 @@
+use std::collections::VecDeque;
+
+use super::*;
+
+#[derive(Component)]
+pub struct DepotGhost;
+
+#[derive(Clone, Copy)]
+pub struct StoredTrain {
+    pub kind: TrainType,
+    pub electric: bool,
+}
+
+// Trains held off the network behind a dead end
+#[derive(Component)]
+pub struct Depot {
+    pub pos: TrackPos,
+    pub stored: Vec<StoredTrain>,
+    pub departures: VecDeque<StoredTrain>,
+}
+
+impl Depot {
+    pub fn new(pos: TrackPos) -> Self {
+        Self {
+            pos,
+            stored: Vec::new(),
+            departures: VecDeque::new(),
+        }
+    }
+
+    // The edge leading out of the depot onto the network
+    pub fn exit(&self, network: &Network) -> Option<TrackEdge> {
+        network.tracks.iter().find_map(|(id, track)| {
+            let edge = if track.segment.end == self.pos {
+                TrackEdge::neg(*id)
+            } else if track.segment.start == self.pos {
+                TrackEdge::pos(*id)
+            } else {
+                return None;
+            };
+            track.segment.allows(edge.direction).then_some(edge)
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stored.is_empty() && self.departures.is_empty()
+    }
+}
+
+pub fn depot_tool(
+    mut commands: Commands,
+    network: Res<Network>,
+    mouse_pos: Res<MousePos>,
+    actions: Actions,
+    depots: Query<(Entity, &Depot)>,
+    ghosts: Query<Entity, With<DepotGhost>>,
+    mut built: Local<usize>,
+) {
+    ghosts.for_each(|e| commands.entity(e).despawn());
+    let mouse_tile = match mouse_pos.0 {
+        Some(pos) => pos_to_tile(pos),
+        None => return,
+    };
+
+    let free = network
+        .dead_ends(mouse_tile)
+        .into_iter()
+        .find(|pos| depots.iter().all(|(_, depot)| depot.pos != *pos));
+    if let Some(pos) = free {
+        commands
+            .spawn_bundle(depot_shape(pos, Color::GRAY))
+            .insert(DepotGhost);
+
+        if actions.just_pressed(Action::Place) {
+            *built += 1;
+            commands
+                .spawn_bundle(depot_shape(pos, Color::rgb(0.6, 0.4, 0.2)))
+                .insert(Depot::new(pos))
+                .insert(Name::new(format!("Depot {}", *built)));
+        }
+    }
+
+    // Depots holding trains have to be emptied first
+    if actions.just_pressed(Action::Remove) {
+        for (entity, depot) in depots.iter() {
+            if depot.pos.tile == mouse_tile && depot.is_empty() {
+                commands.entity(entity).despawn();
+            }
+        }
+    }
+}
+
+pub fn cleanup_depots(mut commands: Commands, ghosts: Query<Entity, With<DepotGhost>>) {
+    ghosts.for_each(|g| commands.entity(g).despawn());
+}
+
+// Depots go with the dead end they were built on, unless they still hold trains
+pub fn prune_depots(
+    mut commands: Commands,
+    network: Res<Network>,
+    depots: Query<(Entity, &Depot)>,
+) {
+    if !network.is_changed() {
+        return;
+    }
+    for (entity, depot) in depots.iter() {
+        if !network.is_dead_end(depot.pos) && depot.is_empty() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+// Self-driving trains that run into a depot are taken off the network
+pub fn store_trains(
+    mut commands: Commands,
+    network: Res<Network>,
+    mut depots: Query<&mut Depot>,
+    trains: Query<(Entity, &Train), (Without<Driving>, Without<Derailed>)>,
+) {
+    for (entity, train) in trains.iter() {
+        let pos = match train.arrived_at(network.as_ref()) {
+            Some(pos) => pos,
+            None => continue,
+        };
+        if let Some(mut depot) = depots.iter_mut().find(|depot| depot.pos == pos) {
+            depot.stored.push(StoredTrain {
+                kind: train.kind,
+                electric: train.electric,
+            });
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+// Send out waiting trains once nothing is on the depot track or the tracks just past it
+pub fn depart_depots(
+    mut commands: Commands,
+    network: Res<Network>,
+    mut depots: Query<&mut Depot>,
+    trains: Query<&Train>,
+) {
+    for mut depot in depots.iter_mut() {
+        if depot.departures.is_empty() {
+            continue;
+        }
+        let exit = match depot.exit(network.as_ref()) {
+            Some(exit) => exit,
+            None => continue,
+        };
+        let mut block = vec![exit.track];
+        if let Some(data) = network.get_data(exit) {
+            let node = data.get_pos(exit.direction);
+            block.extend(network.get_exits(&node).into_iter().map(|(e, _)| e.track));
+        }
+        if trains.iter().any(|train| block.contains(&train.track())) {
+            continue;
+        }
+
+        if let Some(stored) = depot.departures.pop_front() {
+            let train = Train::new(exit, 0., stored.kind, stored.electric);
+            spawn_train(&mut commands, network.as_ref(), train);
+        }
+    }
+}
+
+pub fn depot_ui(
+    mut ctx: ResMut<EguiContext>,
+    settings: Res<TrainSettings>,
+    network: Res<Network>,
+    mut depots: Query<(&Name, &mut Depot)>,
+) {
+    if depots.is_empty() {
+        return;
+    }
+
+    egui::Window::new("Depots").show(ctx.ctx_mut(), |ui| {
+        ui.set_min_width(240.);
+        for (name, mut depot) in depots.iter_mut() {
+            egui::CollapsingHeader::new(name.as_str())
+                .default_open(true)
+                .show(ui, |ui| {
+                    let kind = TRAIN_TYPES[settings.train_type];
+                    if ui.button(format!("Build {}", kind.name)).clicked() {
+                        depot.stored.push(StoredTrain {
+                            kind,
+                            electric: settings.electric,
+                        });
+                    }
+
+                    if !network.is_dead_end(depot.pos) {
+                        ui.label("Cut off from the network.");
+                    }
+                    if depot.stored.is_empty() {
+                        ui.label("No trains stored.");
+                    }
+                    let (mut dispatched, mut scrapped) = (None, None);
+                    for (index, stored) in depot.stored.iter().enumerate() {
+                        ui.horizontal(|ui| {
+                            let electric = if stored.electric { " (electric)" } else { "" };
+                            ui.label(format!("{}{}", stored.kind.name, electric));
+                            if ui.button("Dispatch").clicked() {
+                                dispatched = Some(index);
+                            }
+                            if ui.button("Scrap").clicked() {
+                                scrapped = Some(index);
+                            }
+                        });
+                    }
+                    if let Some(index) = dispatched {
+                        let stored = depot.stored.remove(index);
+                        depot.departures.push_back(stored);
+                    } else if let Some(index) = scrapped {
+                        depot.stored.remove(index);
+                    }
+
+                    if !depot.departures.is_empty() {
+                        ui.horizontal(|ui| {
+                            ui.label(format!(
+                                "{} waiting for the line to clear.",
+                                depot.departures.len()
+                            ));
+                            if ui.button("Cancel").clicked() {
+                                let waiting: Vec<StoredTrain> =
+                                    depot.departures.drain(..).collect();
+                                depot.stored.extend(waiting);
+                            }
+                        });
+                    }
+                });
+        }
+    });
+}
@@ -0,0 +1,98 @@
+use bevy_mod_picking::Hover;
+
+use super::*;
+
+#[derive(Component)]
+pub struct DispatchGhost;
+
+#[derive(Default)]
+pub struct DispatchState {
+    pub train: Option<Entity>,
+    pub status: Option<String>,
+}
+
+pub fn dispatch_tool(
+    mut commands: Commands,
+    mut state: ResMut<DispatchState>,
+    network: Res<Network>,
+    actions: Actions,
+    mut trains: Query<(Entity, &Hover, &Transform, &mut Train), Without<Driving>>,
+    tracks: Query<(&Hover, &NetworkTrack)>,
+    ghosts: Query<Entity, With<DispatchGhost>>,
+) {
+    ghosts.for_each(|e| commands.entity(e).despawn());
+
+    if actions.just_pressed(Action::Remove) || actions.just_pressed(Action::Clear) {
+        state.train = None;
+        state.status = None;
+    }
+
+    if actions.just_pressed(Action::Place) {
+        let hovered_train = trains
+            .iter()
+            .find(|(_, h, _, _)| h.hovered())
+            .map(|(e, ..)| e);
+        let hovered_track = tracks.iter().find(|(h, _)| h.hovered()).map(|(_, t)| t.0);
+        match (hovered_train, state.train, hovered_track) {
+            (Some(train), _, _) => {
+                state.train = Some(train);
+                state.status = None;
+            }
+            (None, Some(train), Some(target)) => {
+                if let Ok((_, _, _, mut train)) = trains.get_mut(train) {
+                    state.status = Some(if train.plan_route(network.as_ref(), target) {
+                        format!("Routed via {} tracks.", train.route().len())
+                    } else {
+                        "No route found.".to_string()
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let (tf, train) = match state.train.and_then(|e| trains.get(e).ok()) {
+        Some((_, _, tf, train)) => (tf, train),
+        None => {
+            state.train = None;
+            return;
+        }
+    };
+
+    let ring = shapes::Circle {
+        radius: 22.,
+        center: tf.translation.truncate(),
+    };
+    commands
+        .spawn_bundle(GeometryBuilder::build_as(
+            &ring,
+            DrawMode::Stroke(StrokeMode {
+                color: Color::YELLOW,
+                options: StrokeOptions::default().with_line_width(3.),
+            }),
+            Transform::from_xyz(0., 0., 200.),
+        ))
+        .insert(DispatchGhost);
+
+    if !train.route().is_empty() {
+        let mut path = PathBuilder::new();
+        for edge in train.route() {
+            if let Some(track) = network.get_data(*edge) {
+                track_path(&mut path, &track.segment);
+            }
+        }
+        commands
+            .spawn_bundle(build_path(path, Color::YELLOW, 4., 15.))
+            .insert(DispatchGhost);
+    }
+}
+
+pub fn cleanup_dispatch(
+    mut commands: Commands,
+    ghosts: Query<Entity, With<DispatchGhost>>,
+    mut state: ResMut<DispatchState>,
+) {
+    ghosts.for_each(|g| commands.entity(g).despawn());
+    state.train = None;
+    state.status = None;
+}
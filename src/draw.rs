@@ -3,14 +3,85 @@ use bevy_prototype_lyon::entity::ShapeBundle;
 
 use super::*;
 
-pub fn draw_track(commands: &mut Commands, id: TrackID, track: &TrackSegment) {
+const TUNNEL_DASHES: f32 = 3.;
+
+pub fn draw_track(commands: &mut Commands, id: TrackID, track: &TrackSegment, electrified: bool) {
+    let z = track.layer.z();
     let mut path_builder = PathBuilder::new();
-    track_path(&mut path_builder, track);
+    let color = match track.layer {
+        TrackLayer::Tunnel => {
+            dashed_track_path(&mut path_builder, track);
+            Color::GRAY
+        }
+        TrackLayer::Ground => {
+            track_path(&mut path_builder, track);
+            Color::WHITE
+        }
+        TrackLayer::Bridge => {
+            track_path(&mut path_builder, track);
+            let mut shadow = PathBuilder::new();
+            track_path(&mut shadow, track);
+            let mut bundle = build_path(shadow, Color::rgba(0., 0., 0., 0.5), 16., z - 1.);
+            bundle.transform.translation += Vec3::new(4., -4., 0.);
+            commands.spawn_bundle(bundle).insert(NetworkTrack(id));
+            Color::WHITE
+        }
+    };
 
     commands
-        .spawn_bundle(build_path(path_builder, Color::WHITE, 8., 10.))
+        .spawn_bundle(build_path(path_builder, color, 8., z))
         .insert_bundle(PickableBundle::default())
         .insert(NetworkTrack(id));
+
+    if let Some(direction) = track.one_way {
+        let mut arrows = PathBuilder::new();
+        one_way_path(&mut arrows, track, direction);
+        commands
+            .spawn_bundle(build_path(arrows, Color::rgb_u8(42, 42, 42), 2., z + 0.5))
+            .insert(NetworkTrack(id));
+    }
+
+    if electrified {
+        let mut masts = PathBuilder::new();
+        catenary_path(&mut masts, track);
+        commands
+            .spawn_bundle(build_path(masts, Color::YELLOW, 2., z + 0.5))
+            .insert(NetworkTrack(id));
+    }
+}
+
+// A mast across the track every tile for overhead wires
+pub fn catenary_path(path: &mut PathBuilder, track: &TrackSegment) {
+    let curve = track.curve();
+    let count = (curve.approximate_length(0.1) / TILE_SIZE).ceil().max(1.);
+    for i in 0..=count as usize {
+        let t = i as f32 / count;
+        let point = curve.sample(t);
+        let tangent = curve.derivative(t);
+        let center = Vec2::new(point.x, point.y);
+        let side = Vec2::new(tangent.x, tangent.y).normalize_or_zero().perp();
+
+        path.move_to(center - side * 8.);
+        path.line_to(center + side * 8.);
+    }
+}
+
+// Chevrons along the track pointing the allowed way
+pub fn one_way_path(path: &mut PathBuilder, track: &TrackSegment, direction: TrackDirection) {
+    let curve = track.curve();
+    let count = (curve.approximate_length(0.1) / TILE_SIZE).ceil().max(1.);
+    for i in 0..count as usize {
+        let t = (i as f32 + 0.5) / count;
+        let point = curve.sample(t);
+        let tangent = curve.derivative(t);
+        let center = Vec2::new(point.x, point.y);
+        let forward = Vec2::new(tangent.x, tangent.y).normalize_or_zero() * direction.signum();
+        let side = forward.perp();
+
+        path.move_to(center - forward * 3. + side * 3.);
+        path.line_to(center + forward * 3.);
+        path.line_to(center - forward * 3. - side * 3.);
+    }
 }
 
 pub fn draw_node(commands: &mut Commands, node: TileIndex) {
@@ -31,12 +102,74 @@ pub fn draw_node(commands: &mut Commands, node: TileIndex) {
         .insert(NetworkNode(node));
 }
 
+pub fn draw_crossing(commands: &mut Commands, id: CrossingID, crossing: &Crossing) {
+    let (a, b) = crossing.tangents;
+    let size = TILE_SIZE / 3.;
+    let mut path = PathBuilder::new();
+    path.move_to(crossing.pos + a * size);
+    path.line_to(crossing.pos + b * size);
+    path.line_to(crossing.pos - a * size);
+    path.line_to(crossing.pos - b * size);
+    path.close();
+
+    commands
+        .spawn_bundle(build_path(path, Color::ORANGE, 3., crossing.layer.z() + 1.))
+        .insert(NetworkCrossing(id));
+}
+
+pub fn buffer_stop_shape(pos: TrackPos, color: Color) -> ShapeBundle {
+    let center = tile_to_center(pos.tile);
+    let across = octant_to_unit(pos.facing).perp() * TILE_SIZE / 3.;
+    let mut path = PathBuilder::new();
+    path.move_to(center - across);
+    path.line_to(center + across);
+    build_path(path, color, 6., 16.)
+}
+
+// A shed sitting behind the track end, open towards the track
+pub fn depot_shape(pos: TrackPos, color: Color) -> ShapeBundle {
+    let center = tile_to_center(pos.tile);
+    let along = octant_to_unit(pos.facing) * TILE_SIZE / 3.;
+    let across = along.perp();
+    let mut path = PathBuilder::new();
+    path.move_to(center + across);
+    path.line_to(center - along * 2. + across);
+    path.line_to(center - along * 2. - across);
+    path.line_to(center - across);
+    build_path(path, color, 4., 16.)
+}
+
 pub fn track_path(path: &mut PathBuilder, track: &TrackSegment) {
     let (start, ctrl_one, ctrl_two, end) = track.control_points();
     path.move_to(start);
     path.cubic_bezier_to(ctrl_one, ctrl_two, end);
 }
 
+// Dashes spaced roughly evenly along the curve, a few per tile
+pub fn dashed_track_path(path: &mut PathBuilder, track: &TrackSegment) {
+    let curve = track.curve();
+    let dashes = (curve.approximate_length(0.1) / TILE_SIZE * TUNNEL_DASHES).ceil() as usize;
+    let step = 1. / dashes.max(1) as f32;
+    for dash in 0..dashes.max(1) {
+        let start = dash as f32 * step;
+        let part = curve.split_range(start..start + step / 2.);
+        path.move_to(Vec2::new(part.from.x, part.from.y));
+        path.cubic_bezier_to(
+            Vec2::new(part.ctrl1.x, part.ctrl1.y),
+            Vec2::new(part.ctrl2.x, part.ctrl2.y),
+            Vec2::new(part.to.x, part.to.y),
+        );
+    }
+}
+
+pub fn rect_path(path: &mut PathBuilder, min: Vec2, max: Vec2) {
+    path.move_to(min);
+    path.line_to(Vec2::new(max.x, min.y));
+    path.line_to(max);
+    path.line_to(Vec2::new(min.x, max.y));
+    path.close();
+}
+
 pub fn build_path(path: PathBuilder, color: Color, width: f32, z: f32) -> ShapeBundle {
     GeometryBuilder::build_as(
         &path.build(),
@@ -0,0 +1,469 @@
+use std::collections::HashMap;
+
+use bevy_prototype_lyon::entity::ShapeBundle;
+
+use super::*;
+
+// Tiles around a station that it collects from and delivers to
+const STATION_RADIUS: i32 = 3;
+// Industries stop producing once this much is piled up with nowhere to go
+const MAX_STOCK: f32 = 100.;
+// Stations stop taking cargo of a kind once this much is waiting for a train
+const MAX_WAITING: u32 = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Cargo {
+    Passengers,
+    Coal,
+    Goods,
+}
+
+impl Cargo {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Cargo::Passengers => "Passengers",
+            Cargo::Coal => "Coal",
+            Cargo::Goods => "Goods",
+        }
+    }
+
+    // Payment per unit per tile travelled
+    pub fn rate(&self) -> f32 {
+        match self {
+            Cargo::Passengers => 0.3,
+            Cargo::Coal => 0.1,
+            Cargo::Goods => 0.2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndustryKind {
+    Town,
+    CoalMine,
+    PowerStation,
+    Factory,
+}
+
+pub const INDUSTRY_KINDS: [IndustryKind; 4] = [
+    IndustryKind::Town,
+    IndustryKind::CoalMine,
+    IndustryKind::PowerStation,
+    IndustryKind::Factory,
+];
+
+impl IndustryKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            IndustryKind::Town => "Town",
+            IndustryKind::CoalMine => "Coal Mine",
+            IndustryKind::PowerStation => "Power Station",
+            IndustryKind::Factory => "Factory",
+        }
+    }
+
+    pub fn produces(&self) -> Option<Cargo> {
+        match self {
+            IndustryKind::Town => Some(Cargo::Passengers),
+            IndustryKind::CoalMine => Some(Cargo::Coal),
+            IndustryKind::PowerStation => None,
+            IndustryKind::Factory => Some(Cargo::Goods),
+        }
+    }
+
+    pub fn accepts(&self) -> &'static [Cargo] {
+        match self {
+            IndustryKind::Town => &[Cargo::Passengers, Cargo::Goods],
+            IndustryKind::CoalMine => &[],
+            IndustryKind::PowerStation => &[Cargo::Coal],
+            IndustryKind::Factory => &[Cargo::Coal],
+        }
+    }
+
+    // Cargo that has to be delivered before anything is produced, one unit for each unit made
+    pub fn consumes(&self) -> Option<Cargo> {
+        match self {
+            IndustryKind::Factory => Some(Cargo::Coal),
+            _ => None,
+        }
+    }
+
+    // Units per second
+    pub fn production(&self) -> f32 {
+        match self {
+            IndustryKind::Town => 2.,
+            IndustryKind::CoalMine => 4.,
+            IndustryKind::PowerStation => 0.,
+            IndustryKind::Factory => 1.,
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        match self {
+            IndustryKind::Town => Color::rgb(0.8, 0.7, 0.5),
+            IndustryKind::CoalMine => Color::rgb(0.25, 0.25, 0.25),
+            IndustryKind::PowerStation => Color::rgb(0.8, 0.3, 0.2),
+            IndustryKind::Factory => Color::rgb(0.4, 0.5, 0.8),
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct Industry {
+    pub kind: IndustryKind,
+    pub tile: TileIndex,
+    pub stock: f32,
+    // Delivered cargo waiting to be processed
+    pub input: f32,
+}
+
+#[derive(Component)]
+pub struct Station {
+    pub tile: TileIndex,
+    pub waiting: HashMap<Cargo, u32>,
+}
+
+impl Station {
+    pub fn covers(&self, tile: TileIndex) -> bool {
+        (self.tile.0 - tile.0).abs() <= STATION_RADIUS
+            && (self.tile.1 - tile.1).abs() <= STATION_RADIUS
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Load {
+    pub cargo: Cargo,
+    pub amount: u32,
+    pub origin: TileIndex,
+}
+
+#[derive(Component)]
+pub struct Wagons {
+    pub capacity: u32,
+    pub load: Option<Load>,
+    // Avoids reloading every frame while sitting on the same station
+    station: Option<Entity>,
+}
+
+pub struct Finances {
+    pub balance: f32,
+    pub income: f32,
+    pub costs: f32,
+    pub construction: f32,
+}
+
+impl Default for Finances {
+    fn default() -> Self {
+        Self {
+            balance: STARTING_FUNDS,
+            income: 0.,
+            costs: 0.,
+            construction: 0.,
+        }
+    }
+}
+
+impl Finances {
+    pub fn earn(&mut self, amount: f32) {
+        self.balance += amount;
+        self.income += amount;
+    }
+
+    pub fn spend(&mut self, amount: f32) {
+        self.balance -= amount;
+        self.costs += amount;
+    }
+
+    // Negative amounts are refunds
+    pub fn build(&mut self, amount: f32) {
+        self.balance -= amount;
+        self.construction += amount;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placeable {
+    Station,
+    Industry(IndustryKind),
+}
+
+impl Placeable {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Placeable::Station => "Station",
+            Placeable::Industry(kind) => kind.name(),
+        }
+    }
+}
+
+pub struct EconomyState {
+    pub placing: Placeable,
+}
+
+impl Default for EconomyState {
+    fn default() -> Self {
+        Self {
+            placing: Placeable::Station,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct EconomyGhost;
+
+fn industry_shape(tile: TileIndex, color: Color) -> ShapeBundle {
+    let square = shapes::RegularPolygon {
+        sides: 4,
+        feature: shapes::RegularPolygonFeature::SideLength(TILE_SIZE * 0.8),
+        ..default()
+    };
+    GeometryBuilder::build_as(
+        &square,
+        DrawMode::Fill(FillMode::color(color)),
+        Transform::from_translation(tile_to_center(tile).extend(1.)),
+    )
+}
+
+fn station_shape(tile: TileIndex, color: Color) -> ShapeBundle {
+    let center = tile_to_center(tile);
+    let half = Vec2::splat(TILE_SIZE / 2.);
+    let mut path = PathBuilder::new();
+    rect_path(&mut path, center - half, center + half);
+    build_path(path, color, 3., 15.)
+}
+
+fn placeable_shape(placing: Placeable, tile: TileIndex, color: Option<Color>) -> ShapeBundle {
+    match placing {
+        Placeable::Station => station_shape(tile, color.unwrap_or(Color::WHITE)),
+        Placeable::Industry(kind) => industry_shape(tile, color.unwrap_or_else(|| kind.color())),
+    }
+}
+
+pub fn economy_tool(
+    mut commands: Commands,
+    state: Res<EconomyState>,
+    network: Res<Network>,
+    world: Res<WorldConfig>,
+    mouse_pos: Res<MousePos>,
+    actions: Actions,
+    industries: Query<(Entity, &Industry)>,
+    stations: Query<(Entity, &Station)>,
+    ghosts: Query<Entity, With<EconomyGhost>>,
+) {
+    ghosts.for_each(|e| commands.entity(e).despawn());
+    let mouse_tile = match mouse_pos.0 {
+        Some(pos) => pos_to_tile(pos),
+        None => return,
+    };
+
+    // Stations go on track, industries off it
+    let on_track = network.get_connections(mouse_tile).contains(&true);
+    let occupied = industries.iter().any(|(_, i)| i.tile == mouse_tile)
+        || stations.iter().any(|(_, s)| s.tile == mouse_tile);
+    let valid = world.contains(mouse_tile)
+        && !occupied
+        && on_track == (state.placing == Placeable::Station);
+    let color = if valid {
+        Color::rgba(1., 1., 1., 0.5)
+    } else {
+        Color::rgba(1., 0., 0., 0.5)
+    };
+    commands
+        .spawn_bundle(placeable_shape(state.placing, mouse_tile, Some(color)))
+        .insert(EconomyGhost);
+
+    if actions.just_pressed(Action::Place) && valid {
+        let mut ec = commands.spawn_bundle(placeable_shape(state.placing, mouse_tile, None));
+        match state.placing {
+            Placeable::Station => ec.insert(Station {
+                tile: mouse_tile,
+                waiting: HashMap::new(),
+            }),
+            Placeable::Industry(kind) => ec.insert(Industry {
+                kind,
+                tile: mouse_tile,
+                stock: 0.,
+                input: 0.,
+            }),
+        };
+    }
+
+    if actions.just_pressed(Action::Remove) {
+        industries
+            .iter()
+            .filter(|(_, i)| i.tile == mouse_tile)
+            .for_each(|(e, _)| commands.entity(e).despawn());
+        stations
+            .iter()
+            .filter(|(_, s)| s.tile == mouse_tile)
+            .for_each(|(e, _)| commands.entity(e).despawn());
+    }
+}
+
+pub fn cleanup_economy(mut commands: Commands, ghosts: Query<Entity, With<EconomyGhost>>) {
+    ghosts.for_each(|g| commands.entity(g).despawn());
+}
+
+pub fn attach_wagons(mut commands: Commands, trains: Query<(Entity, &Train), Added<Train>>) {
+    for (entity, train) in trains.iter() {
+        commands.entity(entity).insert(Wagons {
+            capacity: train.kind.capacity,
+            load: None,
+            station: None,
+        });
+    }
+}
+
+// Industries hand their output to the nearest station in range
+pub fn produce_cargo(
+    time: Res<Time>,
+    mut industries: Query<&mut Industry>,
+    mut stations: Query<&mut Station>,
+) {
+    for mut industry in industries.iter_mut() {
+        let cargo = match industry.kind.produces() {
+            Some(cargo) => cargo,
+            None => continue,
+        };
+        let mut made =
+            (industry.kind.production() * time.delta_seconds()).min(MAX_STOCK - industry.stock);
+        if industry.kind.consumes().is_some() {
+            made = made.min(industry.input);
+            industry.input -= made;
+        }
+        industry.stock += made;
+
+        let tile = industry.tile;
+        let nearest = stations
+            .iter_mut()
+            .filter(|station| station.covers(tile))
+            .min_by_key(|station| {
+                (station.tile.0 - tile.0).abs() + (station.tile.1 - tile.1).abs()
+            });
+        if let Some(mut station) = nearest {
+            let waiting = station.waiting.entry(cargo).or_default();
+            let units = industry
+                .stock
+                .floor()
+                .min(MAX_WAITING.saturating_sub(*waiting) as f32);
+            industry.stock -= units;
+            *waiting += units as u32;
+        }
+    }
+}
+
+// Trains passing through a station drop off what's accepted nearby and pick up what's waiting
+pub fn serve_stations(
+    mut finances: ResMut<Finances>,
+    mut trains: Query<(&Transform, &mut Wagons)>,
+    mut stations: Query<(Entity, &mut Station)>,
+    mut industries: Query<&mut Industry>,
+) {
+    for (tf, mut wagons) in trains.iter_mut() {
+        let tile = pos_to_tile(tf.translation.truncate());
+        let (entity, mut station) = match stations.iter_mut().find(|(_, s)| s.tile == tile) {
+            Some(found) => found,
+            None => {
+                wagons.station = None;
+                continue;
+            }
+        };
+        if wagons.station == Some(entity) {
+            continue;
+        }
+        wagons.station = Some(entity);
+
+        if let Some(load) = wagons.load {
+            let accepted = industries
+                .iter()
+                .any(|i| station.covers(i.tile) && i.kind.accepts().contains(&load.cargo));
+            if accepted && load.origin != station.tile {
+                let distance = IVec2::from(load.origin) - IVec2::from(station.tile);
+                let tiles = distance.x.abs().max(distance.y.abs()) as f32;
+                finances.earn(load.amount as f32 * tiles * load.cargo.rate());
+                wagons.load = None;
+
+                // The nearest industry that processes the cargo stocks up on it
+                let tile = station.tile;
+                let processor = industries
+                    .iter_mut()
+                    .filter(|i| station.covers(i.tile) && i.kind.consumes() == Some(load.cargo))
+                    .min_by_key(|i| (i.tile.0 - tile.0).abs() + (i.tile.1 - tile.1).abs());
+                if let Some(mut industry) = processor {
+                    industry.input = (industry.input + load.amount as f32).min(MAX_STOCK);
+                }
+            }
+        }
+
+        if wagons.load.is_none() {
+            let best = station
+                .waiting
+                .iter()
+                .filter(|(_, amount)| **amount > 0)
+                .max_by_key(|(_, amount)| **amount)
+                .map(|(cargo, _)| *cargo);
+            if let Some(cargo) = best {
+                let origin = station.tile;
+                let waiting = station.waiting.get_mut(&cargo).unwrap();
+                let amount = (*waiting).min(wagons.capacity);
+                *waiting -= amount;
+                wagons.load = Some(Load {
+                    cargo,
+                    amount,
+                    origin,
+                });
+            }
+        }
+    }
+}
+
+pub fn running_costs(time: Res<Time>, mut finances: ResMut<Finances>, trains: Query<&Train>) {
+    let cost: f32 = trains.iter().map(|train| train.kind.running_cost).sum();
+    if cost > 0. {
+        finances.spend(cost * time.delta_seconds());
+    }
+}
+
+pub fn economy_ui(ui: &mut egui::Ui, state: &mut EconomyState, bindings: &Bindings) {
+    egui::ComboBox::from_label("Place")
+        .selected_text(state.placing.name())
+        .show_ui(ui, |ui| {
+            ui.selectable_value(&mut state.placing, Placeable::Station, "Station");
+            for kind in INDUSTRY_KINDS {
+                ui.selectable_value(&mut state.placing, Placeable::Industry(kind), kind.name());
+            }
+        });
+    match state.placing {
+        Placeable::Station => {
+            ui.label(format!(
+                "Serves everything within {} tiles.",
+                STATION_RADIUS
+            ));
+        }
+        Placeable::Industry(kind) => {
+            match (kind.consumes(), kind.produces()) {
+                (Some(input), Some(output)) => {
+                    ui.label(format!(
+                        "Turns delivered {} into {}.",
+                        input.name(),
+                        output.name()
+                    ));
+                }
+                (None, Some(output)) => {
+                    ui.label(format!("Produces {}.", output.name()));
+                }
+                _ => {}
+            }
+            let accepts: Vec<_> = kind.accepts().iter().map(|c| c.name()).collect();
+            if !accepts.is_empty() {
+                ui.label(format!("Accepts {}.", accepts.join(", ")));
+            }
+        }
+    }
+    ui.add_space(4.0);
+    ui.label(format!(
+        "{} to place, stations go on track.",
+        bindings.label(Action::Place)
+    ));
+    ui.label(format!("{} to demolish.", bindings.label(Action::Remove)));
+}
@@ -0,0 +1,318 @@
+use bevy_mod_picking::Hover;
+
+use super::*;
+
+#[derive(Component)]
+pub struct InspectGhost;
+
+#[derive(Default)]
+pub struct InspectorState {
+    pub track: Option<TrackID>,
+    pub train: Option<Entity>,
+}
+
+pub fn inspect_tool(
+    mut commands: Commands,
+    mut state: ResMut<InspectorState>,
+    network: Res<Network>,
+    actions: Actions,
+    tracks: Query<(&Hover, &NetworkTrack)>,
+    trains: Query<(Entity, &Hover, &Transform, Option<&Driving>), With<Train>>,
+    mut follow: ResMut<CameraFollow>,
+    mut active: ResMut<ActiveTrain>,
+    ghosts: Query<Entity, With<InspectGhost>>,
+) {
+    ghosts.for_each(|e| commands.entity(e).despawn());
+
+    // Clicking a train inspects it and follows it with the camera, otherwise pick a track
+    if actions.just_pressed(Action::Place) {
+        if let Some((train, _, _, driving)) = trains.iter().find(|(_, h, _, _)| h.hovered()) {
+            state.train = Some(train);
+            follow.0 = Some(train);
+            if driving.is_some() {
+                active.0 = Some(train);
+            }
+        } else if let Some((_, track)) = tracks.iter().find(|(h, _)| h.hovered()) {
+            state.track = Some(track.0);
+        }
+    }
+    if actions.just_pressed(Action::Remove) || actions.just_pressed(Action::Clear) {
+        state.track = None;
+        state.train = None;
+    }
+
+    if let Some((_, _, tf, _)) = state.train.and_then(|e| trains.get(e).ok()) {
+        let ring = shapes::Circle {
+            radius: 22.,
+            center: tf.translation.truncate(),
+        };
+        commands
+            .spawn_bundle(GeometryBuilder::build_as(
+                &ring,
+                DrawMode::Stroke(StrokeMode {
+                    color: Color::YELLOW,
+                    options: StrokeOptions::default().with_line_width(3.),
+                }),
+                Transform::from_xyz(0., 0., 200.),
+            ))
+            .insert(InspectGhost);
+    }
+
+    if let Some(track) = state.track.and_then(|id| network.get(id)) {
+        let mut path = PathBuilder::new();
+        track_path(&mut path, &track.segment);
+        commands
+            .spawn_bundle(build_path(path, Color::YELLOW, 4., 15.))
+            .insert(InspectGhost);
+    }
+}
+
+fn one_way_label(one_way: Option<TrackDirection>) -> &'static str {
+    match one_way {
+        None => "Both ways",
+        Some(direction) if direction.is_pos() => "Forward",
+        Some(_) => "Backward",
+    }
+}
+
+// Edits a copy of the track's settings and only writes back what changed, so the network
+// isn't flagged as changed every frame while a track is selected
+pub fn track_inspector_ui(
+    mut ctx: ResMut<EguiContext>,
+    mut state: ResMut<InspectorState>,
+    mut network: ResMut<Network>,
+    mut render: EventWriter<NetworkRenderEvent>,
+) {
+    let id = match state.track {
+        Some(id) => id,
+        None => return,
+    };
+    let track = match network.tracks.get(&id) {
+        Some(track) => track,
+        None => {
+            state.track = None;
+            return;
+        }
+    };
+
+    let mut name = track.name.clone();
+    let mut speed_limit = track.speed_limit;
+    let mut electrified = track.electrified;
+    let mut one_way = track.segment.one_way;
+    egui::Window::new("Track Inspector").show(ctx.ctx_mut(), |ui| {
+        ui.set_min_width(240.);
+        let segment = track.segment;
+        ui.label(format!("Track #{}", id));
+        ui.label(format!(
+            "From {:?} facing {}°",
+            segment.start.tile,
+            segment.start.facing.0 as i32 * 45
+        ));
+        ui.label(format!(
+            "To {:?} facing {}°",
+            segment.end.tile,
+            segment.end.facing.0 as i32 * 45
+        ));
+        ui.label(format!("Length: {:.1} tiles", track.length / TILE_SIZE));
+        match track.min_radius {
+            Some(radius) => ui.label(format!("Tightest radius: {:.1} tiles", radius / TILE_SIZE)),
+            None => ui.label("Straight."),
+        };
+        ui.label(format!("Curve speed: {:.0}", track.max_speed));
+        ui.add_space(4.0);
+
+        ui.horizontal(|ui| {
+            ui.label("Name");
+            ui.text_edit_singleline(&mut name);
+        });
+
+        let mut limited = speed_limit.is_some();
+        if ui.checkbox(&mut limited, "Speed limit").changed() {
+            speed_limit = if limited {
+                Some(track.max_speed.round())
+            } else {
+                None
+            };
+        }
+        if let Some(limit) = &mut speed_limit {
+            ui.add(egui::Slider::new(limit, 20.0..=MAX_TRAIN_SPEED).text("Limit"));
+        }
+
+        ui.checkbox(&mut electrified, "Electrified");
+
+        egui::ComboBox::from_label("Direction")
+            .selected_text(one_way_label(one_way))
+            .show_ui(ui, |ui| {
+                for option in [None, Some(TrackDirection::POS), Some(TrackDirection::NEG)] {
+                    ui.selectable_value(&mut one_way, option, one_way_label(option));
+                }
+            });
+    });
+
+    let changed =
+        name != track.name || speed_limit != track.speed_limit || electrified != track.electrified;
+    let redraw = electrified != track.electrified;
+    if changed {
+        if let Some(track) = network.tracks.get_mut(&id) {
+            track.name = name;
+            track.speed_limit = speed_limit;
+            track.electrified = electrified;
+        }
+    }
+    if one_way != network.tracks[&id].segment.one_way {
+        network.set_one_way(id, one_way);
+        render.send(NetworkRenderEvent);
+    } else if redraw {
+        render.send(NetworkRenderEvent);
+    }
+}
+
+fn direction_label(direction: TrackDirection) -> &'static str {
+    if direction.is_pos() {
+        "forward"
+    } else {
+        "backward"
+    }
+}
+
+pub fn train_inspector_ui(
+    mut commands: Commands,
+    mut ctx: ResMut<EguiContext>,
+    mut state: ResMut<InspectorState>,
+    mut active: ResMut<ActiveTrain>,
+    network: Res<Network>,
+    mut trains: Query<(
+        &mut Train,
+        &Transform,
+        Option<&Name>,
+        Option<&mut Driving>,
+        Option<&Derailed>,
+    )>,
+) {
+    let entity = match state.train {
+        Some(entity) => entity,
+        None => return,
+    };
+    let (mut train, tf, name, mut driving, derailed) = match trains.get_mut(entity) {
+        Ok(train) => train,
+        Err(_) => {
+            state.train = None;
+            return;
+        }
+    };
+
+    let mut deleted = false;
+    egui::Window::new("Train Inspector").show(ctx.ctx_mut(), |ui| {
+        ui.set_min_width(240.);
+        let mut label = name.map_or_else(
+            || format!("{} #{}", train.kind.name, entity.id()),
+            |name| name.as_str().to_string(),
+        );
+        ui.horizontal(|ui| {
+            ui.label("Name");
+            if ui.text_edit_singleline(&mut label).changed() {
+                commands.entity(entity).insert(Name::new(label.clone()));
+            }
+        });
+
+        let track = train.track();
+        match network.get(track).filter(|data| !data.name.is_empty()) {
+            Some(data) => ui.label(format!("Track #{} ({})", track, data.name)),
+            None => ui.label(format!("Track #{}", track)),
+        };
+        ui.label(format!(
+            "{:.0}% along, heading {}",
+            train.sample() * 100.,
+            direction_label(train.direction())
+        ));
+        ui.label(format!(
+            "Position: {:?}",
+            pos_to_tile(tf.translation.truncate())
+        ));
+        ui.label(format!(
+            "Speed: {:.0} of {:.0}",
+            train.speed(),
+            train.kind.max_speed
+        ));
+        let mode = match (&driving, derailed) {
+            (_, Some(_)) => "Derailed",
+            (Some(_), _) if active.0 == Some(entity) => "Driven (active)",
+            (Some(_), _) => "Driven",
+            _ => "Self-driving",
+        };
+        ui.label(format!("Mode: {}", mode));
+
+        match train.destination() {
+            Some(track) if train.arrived() && train.held => {
+                ui.label(format!("Arrived at Track #{}, holding.", track));
+                ui.label("Resume to go back to self-driving.");
+            }
+            Some(track) => {
+                ui.label(format!("Destination: Track #{}", track));
+                if !train.route().is_empty() {
+                    let stops: Vec<String> = train
+                        .route()
+                        .iter()
+                        .map(|edge| format!("#{}", edge.track))
+                        .collect();
+                    ui.label(format!("Route: {}", stops.join(" → ")));
+                }
+            }
+            None => {
+                ui.label("No destination.");
+            }
+        }
+        ui.add_space(4.0);
+
+        ui.horizontal(|ui| {
+            let hold = if train.held { "Resume" } else { "Stop" };
+            if ui.button(hold).clicked() {
+                if train.held {
+                    train.release();
+                } else {
+                    train.held = true;
+                }
+            }
+            // Only turn around once stopped
+            if ui
+                .add_enabled(train.speed() < 1., egui::Button::new("Reverse"))
+                .clicked()
+            {
+                train.reverse(driving.as_deref_mut());
+            }
+            if ui.button("Delete").clicked() {
+                deleted = true;
+            }
+        });
+        let mut self_driving = driving.is_none();
+        if ui
+            .add_enabled(
+                derailed.is_none(),
+                egui::Checkbox::new(&mut self_driving, "Self-driving"),
+            )
+            .changed()
+        {
+            if self_driving {
+                commands.entity(entity).remove::<Driving>();
+            } else {
+                commands.entity(entity).insert(Driving(train.front()));
+                active.0 = Some(entity);
+            }
+        }
+    });
+
+    if deleted {
+        commands.entity(entity).despawn();
+        state.train = None;
+    }
+}
+
+pub fn cleanup_inspector(
+    mut commands: Commands,
+    ghosts: Query<Entity, With<InspectGhost>>,
+    mut state: ResMut<InspectorState>,
+) {
+    ghosts.for_each(|g| commands.entity(g).despawn());
+    state.track = None;
+    state.train = None;
+}
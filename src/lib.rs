@@ -1,6 +1,6 @@
 #![allow(clippy::too_many_arguments)]
 
-use bevy::{prelude::*, render::camera::RenderTarget};
+use bevy::{input::InputSystem, prelude::*, render::camera::RenderTarget};
 use bevy_egui::EguiPlugin;
 use bevy_egui::{egui, EguiContext};
 
@@ -34,6 +34,63 @@ use track_types::*;
 mod train_placement_tool;
 use train_placement_tool::*;
 
+mod blueprint;
+use blueprint::*;
+
+mod blueprint_tool;
+use blueprint_tool::*;
+
+mod blueprint_library;
+use blueprint_library::*;
+
+mod autopath;
+use autopath::*;
+
+mod autopath_tool;
+use autopath_tool::*;
+
+mod crossing;
+use crossing::*;
+
+mod inspector;
+use inspector::*;
+
+mod train_types;
+use train_types::*;
+
+mod buffer_stop;
+use buffer_stop::*;
+
+mod routing;
+use routing::*;
+
+mod dispatch;
+use dispatch::*;
+
+mod depot;
+use depot::*;
+
+mod economy;
+use economy::*;
+
+mod costs;
+use costs::*;
+
+mod terrain;
+use terrain::*;
+
+mod world;
+use world::*;
+
+mod minimap;
+use minimap::*;
+
+mod bindings;
+use bindings::*;
+
+mod storage;
+use storage::*;
+
 pub const TITLE: &str = "Track Laying";
 
 #[derive(Debug, Hash, Clone, Copy, PartialEq, Eq)]
@@ -41,6 +98,13 @@ pub enum ControlState {
     None,
     PlacingTracks,
     PlacingTrains,
+    Blueprints,
+    Autopathing,
+    Inspecting,
+    PlacingBufferStops,
+    Dispatching,
+    PlacingDepots,
+    Economy,
 }
 
 #[derive(SystemLabel)]
@@ -68,24 +132,87 @@ pub fn app() -> App {
     .add_startup_system(setup)
     .add_startup_system(setup_network)
     .add_startup_system(setup_track_placement)
+    .add_startup_system(setup_blueprint_library)
     .add_loopless_state(ControlState::PlacingTracks)
     .insert_resource(MousePos(None))
     .insert_resource(PlacementState::default())
+    .insert_resource(BlueprintState::default())
+    .insert_resource(AutopathState::default())
+    .insert_resource(CrossingOccupancy::default())
+    .insert_resource(TrainSettings::default())
+    .insert_resource(ActiveTrain::default())
+    .insert_resource(InspectorState::default())
+    .insert_resource(EndOfLine::default())
+    .insert_resource(DispatchState::default())
+    .insert_resource(PlacementStatus::default())
+    .insert_resource(EconomyState::default())
+    .insert_resource(Finances::default())
+    .insert_resource(GameMode::default())
+    .insert_resource(CostModel::default())
+    .insert_resource(TerrainMap::default())
+    .insert_resource(WorldConfig::default())
+    .insert_resource(CameraFollow::default())
+    .insert_resource(CameraSettings::default())
+    .insert_resource(CameraBookmarks::default())
+    .insert_resource(Bindings::load())
+    .insert_resource(PointerOverUi::default())
+    .insert_resource(RebindState::default())
     .insert_resource(rand::rngs::StdRng::from_entropy())
     .add_event::<TrackPlacementEvent>()
     .add_event::<TrainPlacementEvent>()
     .add_event::<NetworkRenderEvent>()
     .add_exit_system(ControlState::PlacingTracks, cleanup_track_placement)
     .add_exit_system(ControlState::PlacingTrains, cleanup_train_placement)
+    .add_exit_system(ControlState::Blueprints, cleanup_blueprint)
+    .add_exit_system(ControlState::Autopathing, cleanup_autopath)
+    .add_exit_system(ControlState::Inspecting, cleanup_inspector)
+    .add_exit_system(ControlState::PlacingBufferStops, cleanup_buffer_stops)
+    .add_exit_system(ControlState::Dispatching, cleanup_dispatch)
+    .add_exit_system(ControlState::PlacingDepots, cleanup_depots)
+    .add_exit_system(ControlState::Economy, cleanup_economy)
+    .add_system_to_stage(CoreStage::PreUpdate, update_pointer_over_ui)
+    .add_system_to_stage(
+        CoreStage::PreUpdate,
+        capture_rebind
+            .after(InputSystem)
+            .after(update_pointer_over_ui),
+    )
     .add_system(camera_pan.before(mouse_to_world))
     .add_system(camera_zoom.before(mouse_to_world))
+    .add_system(camera_keys.before(mouse_to_world))
+    .add_system(camera_bookmarks.before(mouse_to_world))
+    .add_system(clamp_camera.before(mouse_to_world))
+    .add_system(
+        follow_camera
+            .after(camera_pan)
+            .after(camera_zoom)
+            .after(camera_keys)
+            .before(mouse_to_world),
+    )
+    .add_system(cycle_follow)
+    .add_system(camera_ui)
+    .add_system(minimap_ui)
+    .add_system(bindings_ui)
     .add_system(mouse_to_world.label(SystemLabels::MouseToWorld))
     .add_system(control_ui)
+    .add_system(budget_ui)
     .add_system(place_tracks)
     .add_system(place_train)
+    .add_system(release_crossings.before(drive_trains).before(update_trains))
     .add_system(drive_trains)
+    .add_system(cycle_driven.before(drive_trains))
+    .add_system(mark_active_train.after(drive_trains))
     .add_system(update_trains)
+    .add_system(store_trains.after(update_trains))
+    .add_system(depart_depots)
+    .add_system(prune_depots)
+    .add_system(attach_wagons)
+    .add_system(produce_cargo)
+    .add_system(serve_stations.after(update_trains).after(drive_trains))
+    .add_system(running_costs)
     .add_system(extract_network_to_mesh.after(place_tracks))
+    .add_system(render_terrain)
+    .add_system(draw_world_border)
     .add_system(highlight.after(mouse_to_world))
     .add_system_set(
         ConditionSet::new()
@@ -101,6 +228,60 @@ pub fn app() -> App {
             .run_in_state(ControlState::PlacingTrains)
             .with_system(train_placement_tool)
             .with_system(remove_trains)
+            .with_system(train_stats_ui)
+            .into(),
+    )
+    .add_system_set(
+        ConditionSet::new()
+            .after(SystemLabels::MouseToWorld)
+            .run_in_state(ControlState::Blueprints)
+            .with_system(blueprint_tool)
+            .with_system(blueprint_library_ui)
+            .into(),
+    )
+    .add_system_set(
+        ConditionSet::new()
+            .after(SystemLabels::MouseToWorld)
+            .run_in_state(ControlState::Autopathing)
+            .with_system(autopath_tool)
+            .into(),
+    )
+    .add_system_set(
+        ConditionSet::new()
+            .after(SystemLabels::MouseToWorld)
+            .run_in_state(ControlState::Inspecting)
+            .with_system(inspect_tool)
+            .with_system(track_inspector_ui)
+            .with_system(train_inspector_ui)
+            .into(),
+    )
+    .add_system_set(
+        ConditionSet::new()
+            .after(SystemLabels::MouseToWorld)
+            .run_in_state(ControlState::PlacingBufferStops)
+            .with_system(buffer_stop_tool)
+            .into(),
+    )
+    .add_system_set(
+        ConditionSet::new()
+            .after(SystemLabels::MouseToWorld)
+            .run_in_state(ControlState::Dispatching)
+            .with_system(dispatch_tool)
+            .into(),
+    )
+    .add_system_set(
+        ConditionSet::new()
+            .after(SystemLabels::MouseToWorld)
+            .run_in_state(ControlState::PlacingDepots)
+            .with_system(depot_tool)
+            .with_system(depot_ui)
+            .into(),
+    )
+    .add_system_set(
+        ConditionSet::new()
+            .after(SystemLabels::MouseToWorld)
+            .run_in_state(ControlState::Economy)
+            .with_system(economy_tool)
             .into(),
     );
     app
@@ -122,7 +303,7 @@ fn setup(mut commands: Commands) {
         .spawn_bundle(GeometryBuilder::build_as(
             &square,
             DrawMode::Fill(FillMode::color(Color::rgba(1., 1., 1., 0.01))),
-            Transform::default(),
+            Transform::from_xyz(0., 0., 0.05),
         ))
         .insert(Highlight);
 }
@@ -175,17 +356,63 @@ fn highlight(
     }
 }
 
+fn layer_ui(ui: &mut egui::Ui, layer: &mut TrackLayer) {
+    ui.horizontal(|ui| {
+        ui.selectable_value(layer, TrackLayer::Tunnel, "Tunnel");
+        ui.selectable_value(layer, TrackLayer::Ground, "Ground");
+        ui.selectable_value(layer, TrackLayer::Bridge, "Bridge");
+    });
+}
+
+fn geometry_ui(ui: &mut egui::Ui, geometry: &mut TrackGeometry) {
+    let mut transition = *geometry == TrackGeometry::Transition;
+    if ui.checkbox(&mut transition, "Transition curves").changed() {
+        *geometry = if transition {
+            TrackGeometry::Transition
+        } else {
+            TrackGeometry::Standard
+        };
+    }
+}
+
 pub fn control_ui(
     mut commands: Commands,
     state: Res<CurrentState<ControlState>>,
     mut ctx: ResMut<EguiContext>,
     mut params: ResMut<TrackParams>,
+    mut blueprints: ResMut<BlueprintState>,
+    mut train_settings: ResMut<TrainSettings>,
+    mut end_of_line: ResMut<EndOfLine>,
+    autopath: Res<AutopathState>,
+    dispatch: Res<DispatchState>,
+    mut economy: ResMut<EconomyState>,
+    bindings: Res<Bindings>,
+    mut terrain: ResMut<TerrainMap>,
+    mut world: ResMut<WorldConfig>,
+    network: Res<Network>,
+    mut placement_status: ResMut<PlacementStatus>,
+    industries: Query<(), With<Industry>>,
 ) {
     egui::Window::new("Controls").show(ctx.ctx_mut(), |ui| {
         ui.set_min_width(240.);
+        let place = bindings.label(Action::Place);
+        let remove = bindings.label(Action::Remove);
         ui.label("Scroll to zoom.");
-        ui.label("Middle mouse to pan.");
-        ui.label("Drive trains with WASD.");
+        ui.label(format!(
+            "{} or the minimap to pan.",
+            bindings.label(Action::Pan)
+        ));
+        ui.label(format!(
+            "Drive trains with {}, {}, {}, {}.",
+            bindings.label(Action::Forward),
+            bindings.label(Action::SteerLeft),
+            bindings.label(Action::Backward),
+            bindings.label(Action::SteerRight)
+        ));
+        ui.label(format!(
+            "{} switches between driven trains.",
+            bindings.label(Action::CycleDriven)
+        ));
         ui.add_space(4.0);
 
         ui.horizontal(|ui| {
@@ -193,27 +420,190 @@ pub fn control_ui(
             ui.selectable_value(&mut mut_state, ControlState::None, "None");
             ui.selectable_value(&mut mut_state, ControlState::PlacingTracks, "Tracks");
             ui.selectable_value(&mut mut_state, ControlState::PlacingTrains, "Trains");
+            ui.selectable_value(&mut mut_state, ControlState::Blueprints, "Blueprints");
+            ui.selectable_value(&mut mut_state, ControlState::Autopathing, "Autopath");
+            ui.selectable_value(&mut mut_state, ControlState::Inspecting, "Inspect");
+            ui.selectable_value(&mut mut_state, ControlState::PlacingBufferStops, "Buffers");
+            ui.selectable_value(&mut mut_state, ControlState::Dispatching, "Dispatch");
+            ui.selectable_value(&mut mut_state, ControlState::PlacingDepots, "Depots");
+            ui.selectable_value(&mut mut_state, ControlState::Economy, "Economy");
             if mut_state != state.0 {
                 commands.insert_resource(NextState(mut_state));
+                placement_status.0 = None;
             }
         });
+        if let Some(status) = &placement_status.0 {
+            ui.colored_label(egui::Color32::LIGHT_RED, status);
+        }
 
         ui.add_space(4.0);
 
         match state.0 {
-            ControlState::None => {}
+            ControlState::None => {
+                let locked = !network.tracks.is_empty() || !industries.is_empty();
+                terrain_ui(ui, &mut terrain, locked);
+                world_ui(ui, &mut world, locked);
+            }
             ControlState::PlacingTracks => {
                 ui.add(egui::Slider::new(&mut params.radius, 2.5..=20.0).text("Radius"));
+                ui.add(egui::Slider::new(&mut params.track_count, 1..=4).text("Tracks"));
+                ui.add_enabled(
+                    params.track_count > 1,
+                    egui::Slider::new(&mut params.spacing, 1..=4).text("Spacing"),
+                );
+                ui.add_enabled(
+                    params.track_count > 1,
+                    egui::Checkbox::new(&mut params.parallel_left, "Parallel tracks on the left"),
+                );
+                ui.checkbox(&mut params.full_path, "Lay full path");
+                geometry_ui(ui, &mut params.geometry);
+                layer_ui(ui, &mut params.layer);
+                ui.checkbox(&mut params.one_way, "One-way");
                 ui.add_space(4.0);
-                ui.label("Left-click to place.");
-                ui.label("Right-click to cancel and erase.");
-                ui.label("Hold Shift to allow S-bends.");
+                ui.label(format!("{} to place.", place));
+                if params.full_path {
+                    ui.label(format!(
+                        "Drag and press {} to add waypoints.",
+                        bindings.label(Action::Waypoint)
+                    ));
+                }
+                ui.label(format!("{} to cancel and erase.", remove));
+                ui.label(format!(
+                    "Hold {} to allow S-bends.",
+                    bindings.label(Action::Alternate)
+                ));
+                ui.label(format!(
+                    "{} for bridges, {} for tunnels.",
+                    bindings.label(Action::Bridge),
+                    bindings.label(Action::Tunnel)
+                ));
             }
             ControlState::PlacingTrains => {
-                ui.label("Left-click to place.");
-                ui.label("Right-click to destroy.");
-                ui.label("Hold Shift for self-driving.");
+                ui.label(format!("{} to place.", place));
+                ui.label(format!("{} to destroy.", remove));
+                ui.label(format!(
+                    "Hold {} for self-driving.",
+                    bindings.label(Action::Alternate)
+                ));
+                ui.add_space(4.0);
+                egui::ComboBox::from_label("Type")
+                    .selected_text(TRAIN_TYPES[train_settings.train_type].name)
+                    .show_ui(ui, |ui| {
+                        for (index, train_type) in TRAIN_TYPES.iter().enumerate() {
+                            ui.selectable_value(
+                                &mut train_settings.train_type,
+                                index,
+                                train_type.name,
+                            );
+                        }
+                    });
+                ui.checkbox(&mut train_settings.electric, "Electric");
+                ui.checkbox(&mut train_settings.derailments, "Derailments");
+                ui.label("Trains turn orange above the speed limit.");
+            }
+            ControlState::Blueprints => {
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(blueprints.has_selection(), egui::Button::new("Copy"))
+                        .clicked()
+                    {
+                        blueprints.copy(network.as_ref());
+                    }
+                    if ui
+                        .add_enabled(blueprints.clipboard.is_some(), egui::Button::new("Paste"))
+                        .clicked()
+                    {
+                        blueprints.paste();
+                    }
+                });
+                ui.add_space(4.0);
+                if blueprints.pasting {
+                    let orientation = blueprints.orientation;
+                    ui.label(format!("Rotation: {}°", orientation.degrees()));
+                    if orientation.mirrored {
+                        ui.label("Mirrored.");
+                    }
+                    ui.label(format!("{} to place.", place));
+                    let rotate = bindings.label(Action::Rotate);
+                    ui.label(format!(
+                        "{} to rotate, {}+{} to rotate back.",
+                        rotate,
+                        bindings.label(Action::Alternate),
+                        rotate
+                    ));
+                    ui.label(format!("{} to mirror.", bindings.label(Action::Mirror)));
+                    ui.label(format!("{} to stop pasting.", remove));
+                } else {
+                    ui.label(format!("{} and drag to select tracks.", place));
+                    let command = bindings.label(Action::Command);
+                    ui.label(format!(
+                        "{}+{} to copy, {}+{} to paste.",
+                        command,
+                        bindings.label(Action::Copy),
+                        command,
+                        bindings.label(Action::Paste)
+                    ));
+                    ui.label(format!("{} to clear selection.", remove));
+                }
+            }
+            ControlState::Autopathing => {
+                ui.add(egui::Slider::new(&mut params.radius, 2.5..=20.0).text("Radius"));
+                geometry_ui(ui, &mut params.geometry);
+                layer_ui(ui, &mut params.layer);
+                ui.checkbox(&mut params.one_way, "One-way");
+                ui.add_space(4.0);
+                if autopath.start.is_none() {
+                    ui.label(format!("{} the start tile and pick a facing.", place));
+                } else if autopath.end.is_none() {
+                    ui.label(format!("{} the end tile and pick a facing.", place));
+                } else {
+                    ui.label(format!("{} to place the route.", place));
+                }
+                ui.label(format!("{} to cancel.", remove));
+                if let Some(status) = &autopath.status {
+                    ui.label(status);
+                }
+            }
+            ControlState::Inspecting => {
+                ui.label(format!("{} a track to inspect it.", place));
+                ui.label(format!(
+                    "{} a train to inspect and follow it, {} to cycle.",
+                    place,
+                    bindings.label(Action::FollowNext)
+                ));
+                ui.label(format!("{} to clear.", remove));
+                ui.label("Electric trains only run on electrified track.");
+            }
+            ControlState::PlacingBufferStops => {
+                ui.checkbox(&mut end_of_line.reverse, "Reverse at buffer stops");
+                ui.add_enabled(
+                    end_of_line.reverse,
+                    egui::Slider::new(&mut end_of_line.pause, 0.0..=10.0).text("Pause (s)"),
+                );
+                ui.add_space(4.0);
+                ui.label(format!("{} a dead end to place a buffer stop.", place));
+                ui.label(format!("{} to remove.", remove));
+            }
+            ControlState::Dispatching => {
+                if dispatch.train.is_none() {
+                    ui.label(format!("{} a self-driving train to select it.", place));
+                } else {
+                    ui.label(format!("{} a track to send the train there.", place));
+                    ui.label("Trains reverse at dead ends when the route needs it.");
+                    ui.label("Arrived trains hold until resumed from the inspector.");
+                }
+                ui.label(format!("{} to clear.", remove));
+                if let Some(status) = &dispatch.status {
+                    ui.label(status);
+                }
+            }
+            ControlState::PlacingDepots => {
+                ui.label(format!("{} a dead end to build a depot.", place));
+                ui.label(format!("{} to demolish an empty depot.", remove));
+                ui.label("Self-driving trains that reach a depot are stored.");
+                ui.label("New trains use the type chosen under Trains.");
             }
+            ControlState::Economy => economy_ui(ui, &mut economy, &bindings),
         };
     });
 }
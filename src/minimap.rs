@@ -0,0 +1,88 @@
+use bevy_egui::egui::{Color32, Pos2, Rect, Sense, Stroke};
+
+use super::*;
+
+// Longest side of the minimap in screen pixels
+const MINIMAP_SIZE: f32 = 200.;
+// Points sampled along each track
+const TRACK_SAMPLES: usize = 4;
+
+pub fn minimap_ui(
+    mut ctx: ResMut<EguiContext>,
+    network: Res<Network>,
+    world: Res<WorldConfig>,
+    mut follow: ResMut<CameraFollow>,
+    mut cameras: Query<(&mut Transform, &OrthographicProjection), With<Camera>>,
+    trains: Query<&Transform, (With<Train>, Without<Camera>)>,
+) {
+    let (min, max) = world.rect();
+    let extent = max - min;
+    let size = extent / extent.max_element() * MINIMAP_SIZE;
+
+    egui::Window::new("Minimap")
+        .anchor(egui::Align2::RIGHT_BOTTOM, [-10., -10.])
+        .resizable(false)
+        .show(ctx.ctx_mut(), |ui| {
+            let (response, painter) =
+                ui.allocate_painter(egui::Vec2::new(size.x, size.y), Sense::click_and_drag());
+            let rect = response.rect;
+
+            // World y points up, screen y points down
+            let to_screen = |pos: Vec2| {
+                let t = (pos - min) / extent;
+                Pos2::new(
+                    rect.left() + t.x * rect.width(),
+                    rect.bottom() - t.y * rect.height(),
+                )
+            };
+            let to_world = |pos: Pos2| {
+                let t = Vec2::new(
+                    (pos.x - rect.left()) / rect.width(),
+                    (rect.bottom() - pos.y) / rect.height(),
+                );
+                min + t * extent
+            };
+
+            painter.rect_filled(rect, 0., Color32::from_gray(30));
+            for track in network.tracks.values() {
+                let points: Vec<Pos2> = (0..=TRACK_SAMPLES)
+                    .map(|step| {
+                        let point = track.curve.sample(step as f32 / TRACK_SAMPLES as f32);
+                        to_screen(Vec2::new(point.x, point.y))
+                    })
+                    .collect();
+                let color = match track.segment.layer {
+                    TrackLayer::Tunnel => Color32::from_gray(100),
+                    _ => Color32::from_gray(200),
+                };
+                for pair in points.windows(2) {
+                    painter.line_segment([pair[0], pair[1]], Stroke::new(1.0_f32, color));
+                }
+            }
+            for tf in trains.iter() {
+                painter.circle_filled(
+                    to_screen(tf.translation.truncate()),
+                    2.5,
+                    Color32::from_rgb(80, 120, 255),
+                );
+            }
+
+            for (mut tf, proj) in &mut cameras {
+                let center = tf.translation.truncate();
+                let half = Vec2::new(proj.right, proj.top) * proj.scale;
+                let view = Rect::from_two_pos(to_screen(center - half), to_screen(center + half));
+                painter.rect_stroke(
+                    view.intersect(rect),
+                    0.,
+                    Stroke::new(1.0_f32, Color32::YELLOW),
+                );
+
+                if let Some(pointer) = response.interact_pointer_pos() {
+                    let target = to_world(pointer);
+                    tf.translation = target.extend(tf.translation.z);
+                    clamp_center(&mut tf, &world);
+                    follow.0 = None;
+                }
+            }
+        });
+}
@@ -0,0 +1,150 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use bevy::utils::FloatOrd;
+
+use super::*;
+
+// Extra distance a reversal costs, so through routes are preferred when they exist
+const REVERSE_PENALTY: f32 = 10. * TILE_SIZE;
+
+// Dijkstra over directed track edges from the train's position to any edge of the target
+// track. Trains may reverse at dead ends, which shows up as an edge followed by its reverse.
+pub fn find_train_route<F>(
+    network: &Network,
+    start: TrackEdge,
+    sample: f32,
+    target: TrackID,
+    can_enter: F,
+) -> Option<Vec<TrackEdge>>
+where
+    F: Fn(&TrackData) -> bool,
+{
+    if start.track == target {
+        return Some(Vec::new());
+    }
+    let start_data = network.get_data(start)?;
+
+    let mut open = BinaryHeap::new();
+    let mut costs: HashMap<TrackEdge, f32> = HashMap::new();
+    let mut came_from: HashMap<TrackEdge, TrackEdge> = HashMap::new();
+
+    let start_cost = (1. - sample) * start_data.length;
+    costs.insert(start, start_cost);
+    open.push(Reverse((FloatOrd(start_cost), start)));
+
+    while let Some(Reverse((FloatOrd(cost), edge))) = open.pop() {
+        if edge.track == target {
+            let mut route = vec![edge];
+            let mut current = edge;
+            while let Some(prev) = came_from.get(&current) {
+                current = *prev;
+                route.push(current);
+            }
+            route.pop();
+            route.reverse();
+            return Some(route);
+        }
+        if costs.get(&edge).map_or(false, |best| cost > *best) {
+            continue;
+        }
+
+        let data = match network.get_data(edge) {
+            Some(data) => data,
+            None => continue,
+        };
+        let exits: Vec<(TrackEdge, f32)> = network
+            .get_exits(&data.get_pos(edge.direction))
+            .into_iter()
+            .filter(|(_, next)| can_enter(next))
+            .map(|(next_edge, next)| (*next_edge, cost + next.length))
+            .collect();
+        let moves = if exits.is_empty() && data.segment.allows(edge.direction.inverse()) {
+            vec![(edge.reversed(), cost + REVERSE_PENALTY + data.length)]
+        } else {
+            exits
+        };
+
+        for (next_edge, next_cost) in moves {
+            if costs.get(&next_edge).map_or(true, |best| next_cost < *best) {
+                costs.insert(next_edge, next_cost);
+                came_from.insert(next_edge, edge);
+                open.push(Reverse((FloatOrd(next_cost), next_edge)));
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn straight(network: &mut Network, from: TileIndex, to: TileIndex) -> TrackID {
+        network.add_track(TrackSegment::new(
+            TrackPos::new(from, Octant(0)),
+            TrackPos::new(to, Octant(4)),
+        ))
+    }
+
+    // The edge of a track that runs towards the given tile
+    fn towards(network: &Network, track: TrackID, tile: TileIndex) -> TrackEdge {
+        [TrackEdge::pos(track), TrackEdge::neg(track)]
+            .into_iter()
+            .find(|edge| {
+                network
+                    .get_data(*edge)
+                    .unwrap()
+                    .get_pos(edge.direction)
+                    .tile
+                    == tile
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn routes_along_a_line() {
+        let mut network = Network::default();
+        let a = straight(&mut network, (0, 0), (0, 3));
+        let b = straight(&mut network, (0, 3), (0, 6));
+        let c = straight(&mut network, (0, 6), (0, 9));
+        let start = towards(&network, a, (0, 3));
+        let route = find_train_route(&network, start, 0., c, |_| true).unwrap();
+        let tracks: Vec<TrackID> = route.iter().map(|edge| edge.track).collect();
+        assert_eq!(tracks, vec![b, c]);
+    }
+
+    #[test]
+    fn reverses_at_a_dead_end() {
+        let mut network = Network::default();
+        let a = straight(&mut network, (0, 0), (0, 3));
+        let b = straight(&mut network, (0, 3), (0, 6));
+        let start = towards(&network, b, (0, 6));
+        let route = find_train_route(&network, start, 0.5, a, |_| true).unwrap();
+        assert_eq!(route, vec![start.reversed(), towards(&network, a, (0, 0))]);
+    }
+
+    #[test]
+    fn already_on_the_target() {
+        let mut network = Network::default();
+        let a = straight(&mut network, (0, 0), (0, 3));
+        let start = TrackEdge::pos(a);
+        assert_eq!(
+            find_train_route(&network, start, 0., a, |_| true),
+            Some(Vec::new())
+        );
+    }
+
+    #[test]
+    fn skips_tracks_the_train_cannot_enter() {
+        let mut network = Network::default();
+        let a = straight(&mut network, (0, 0), (0, 3));
+        let b = straight(&mut network, (0, 3), (0, 6));
+        let c = straight(&mut network, (0, 6), (0, 9));
+        let start = towards(&network, a, (0, 3));
+        let blocked = network.get(b).unwrap();
+        let route = find_train_route(&network, start, 0., c, |data| !std::ptr::eq(data, blocked));
+        assert_eq!(route, None);
+    }
+}
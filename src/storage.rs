@@ -0,0 +1,83 @@
+// Small text files saved between sessions, on disk natively and in local storage on the web
+
+#[cfg(not(target_arch = "wasm32"))]
+mod backend {
+    use std::fs;
+    use std::path::Path;
+
+    pub fn read_text(path: &str) -> Option<String> {
+        fs::read_to_string(path).ok()
+    }
+
+    pub fn write_text(path: &str, text: &str) -> Result<(), String> {
+        if let Some(parent) = Path::new(path).parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        fs::write(path, text).map_err(|e| e.to_string())
+    }
+
+    pub fn remove_text(path: &str) -> Result<(), String> {
+        fs::remove_file(path).map_err(|e| e.to_string())
+    }
+
+    pub fn exists(path: &str) -> bool {
+        Path::new(path).exists()
+    }
+
+    pub fn list_texts(dir: &str) -> Vec<String> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+        entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|path| path.extension().and_then(|e| e.to_str()) == Some("ron"))
+            .filter_map(|path| path.to_str().map(str::to_string))
+            .collect()
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod backend {
+    fn local_storage() -> Result<web_sys::Storage, String> {
+        web_sys::window()
+            .and_then(|window| window.local_storage().ok().flatten())
+            .ok_or_else(|| "Local storage is unavailable".to_string())
+    }
+
+    pub fn read_text(path: &str) -> Option<String> {
+        local_storage().ok()?.get_item(path).ok()?
+    }
+
+    pub fn write_text(path: &str, text: &str) -> Result<(), String> {
+        local_storage()?
+            .set_item(path, text)
+            .map_err(|_| "Couldn't write to local storage".to_string())
+    }
+
+    pub fn remove_text(path: &str) -> Result<(), String> {
+        local_storage()?
+            .remove_item(path)
+            .map_err(|_| "Couldn't remove from local storage".to_string())
+    }
+
+    pub fn exists(path: &str) -> bool {
+        read_text(path).is_some()
+    }
+
+    // Keys stand in for paths, so a directory is just a key prefix
+    pub fn list_texts(dir: &str) -> Vec<String> {
+        let storage = match local_storage() {
+            Ok(storage) => storage,
+            Err(_) => return Vec::new(),
+        };
+        let prefix = format!("{}/", dir);
+        (0..storage.length().unwrap_or(0))
+            .filter_map(|index| storage.key(index).ok().flatten())
+            .filter(|key| key.starts_with(&prefix) && key.ends_with(".ron"))
+            .collect()
+    }
+}
+
+pub use backend::*;
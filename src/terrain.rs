@@ -0,0 +1,275 @@
+use std::collections::HashSet;
+
+use super::*;
+
+// Tiles per side of a rendered chunk
+const CHUNK_SIZE: i32 = 16;
+// Stop streaming in chunks when zoomed out this far
+const MAX_VISIBLE_CHUNKS: i32 = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Terrain {
+    Grass,
+    Water,
+    Forest,
+    Mountain,
+}
+
+impl Terrain {
+    pub fn color(&self) -> Color {
+        match self {
+            Terrain::Grass => Color::rgb(0.19, 0.25, 0.17),
+            Terrain::Water => Color::rgb(0.12, 0.2, 0.32),
+            Terrain::Forest => Color::rgb(0.1, 0.18, 0.11),
+            Terrain::Mountain => Color::rgb(0.36, 0.33, 0.3),
+        }
+    }
+
+    // Relative cost of laying ground level track through the tile
+    pub fn cost_multiplier(&self) -> f32 {
+        match self {
+            Terrain::Grass | Terrain::Water => 1.,
+            Terrain::Forest => 1.5,
+            Terrain::Mountain => 3.,
+        }
+    }
+}
+
+const TERRAIN_TYPES: [Terrain; 4] = [
+    Terrain::Grass,
+    Terrain::Water,
+    Terrain::Forest,
+    Terrain::Mountain,
+];
+
+// Everything is derived from the seed so the same seed always gives the same map
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TerrainMap {
+    pub seed: u64,
+    pub visible: bool,
+}
+
+impl Default for TerrainMap {
+    fn default() -> Self {
+        Self {
+            seed: 1,
+            visible: true,
+        }
+    }
+}
+
+fn hash(seed: u64, x: i32, y: i32) -> f32 {
+    // splitmix64 over the seed and lattice point
+    let mut z = seed
+        .wrapping_add((x as u32 as u64) << 32 | y as u32 as u64)
+        .wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    (z >> 40) as f32 / (1u64 << 24) as f32
+}
+
+fn value_noise(seed: u64, pos: Vec2) -> f32 {
+    let cell = pos.floor();
+    let t = pos - cell;
+    let t = t * t * (Vec2::splat(3.) - 2. * t);
+    let (x, y) = (cell.x as i32, cell.y as i32);
+
+    let bottom = hash(seed, x, y) * (1. - t.x) + hash(seed, x + 1, y) * t.x;
+    let top = hash(seed, x, y + 1) * (1. - t.x) + hash(seed, x + 1, y + 1) * t.x;
+    bottom * (1. - t.y) + top * t.y
+}
+
+// Octaves of value noise, normalized to 0..1
+fn fractal_noise(seed: u64, pos: Vec2, octaves: u32) -> f32 {
+    let (mut total, mut amplitude, mut scale, mut norm) = (0., 1., 1., 0.);
+    for octave in 0..octaves {
+        total += value_noise(seed.wrapping_add(octave as u64), pos * scale) * amplitude;
+        norm += amplitude;
+        amplitude *= 0.5;
+        scale *= 2.;
+    }
+    total / norm
+}
+
+impl TerrainMap {
+    pub fn terrain_at(&self, tile: TileIndex) -> Terrain {
+        let pos = tile_to_vec(tile).as_vec2();
+        let elevation = fractal_noise(self.seed, pos / 24., 4);
+        let moisture = fractal_noise(self.seed.wrapping_mul(31).wrapping_add(7), pos / 12., 3);
+        if elevation < 0.32 {
+            Terrain::Water
+        } else if elevation > 0.68 {
+            Terrain::Mountain
+        } else if moisture > 0.6 {
+            Terrain::Forest
+        } else {
+            Terrain::Grass
+        }
+    }
+
+    // Only bridges can cross water
+    pub fn blocks(&self, segment: &TrackSegment) -> bool {
+        segment.layer != TrackLayer::Bridge
+            && segment
+                .tiles()
+                .into_iter()
+                .any(|tile| self.terrain_at(tile) == Terrain::Water)
+    }
+
+    // Bridges and tunnels already pay for their structure, so terrain only affects ground track
+    pub fn cost_multiplier(&self, segment: &TrackSegment) -> f32 {
+        if segment.layer != TrackLayer::Ground {
+            return 1.;
+        }
+        let tiles = segment.tiles();
+        let total: f32 = tiles
+            .iter()
+            .map(|tile| self.terrain_at(*tile).cost_multiplier())
+            .sum();
+        total / tiles.len().max(1) as f32
+    }
+}
+
+#[derive(Component)]
+pub struct TerrainChunk(IVec2);
+
+fn spawn_chunk(commands: &mut Commands, map: &TerrainMap, world: &WorldConfig, chunk: IVec2) {
+    let origin = chunk * CHUNK_SIZE;
+    for terrain in TERRAIN_TYPES {
+        // Merge runs along each row to keep the mesh small
+        let mut path = PathBuilder::new();
+        let mut empty = true;
+        for y in 0..CHUNK_SIZE {
+            let mut x = 0;
+            while x < CHUNK_SIZE {
+                let start = x;
+                let tile = |x: i32| (origin.x + x, origin.y + y);
+                while x < CHUNK_SIZE
+                    && world.contains(tile(x))
+                    && map.terrain_at(tile(x)) == terrain
+                {
+                    x += 1;
+                }
+                if x > start {
+                    let min = tile_vec_to_pos(origin + IVec2::new(start, y));
+                    let max = tile_vec_to_pos(origin + IVec2::new(x, y + 1));
+                    rect_path(&mut path, min, max);
+                    empty = false;
+                } else {
+                    x += 1;
+                }
+            }
+        }
+        if empty {
+            continue;
+        }
+
+        commands
+            .spawn_bundle(GeometryBuilder::build_as(
+                &path.build(),
+                DrawMode::Fill(FillMode::color(terrain.color())),
+                Transform::default(),
+            ))
+            .insert(TerrainChunk(chunk));
+    }
+}
+
+// Streams chunks in around the camera and drops the ones that scroll away
+pub fn render_terrain(
+    mut commands: Commands,
+    map: Res<TerrainMap>,
+    world: Res<WorldConfig>,
+    cameras: Query<(&Transform, &OrthographicProjection), With<Camera>>,
+    chunks: Query<(Entity, &TerrainChunk)>,
+    mut loaded: Local<HashSet<IVec2>>,
+) {
+    if map.is_changed() || world.is_changed() {
+        chunks.for_each(|(e, _)| commands.entity(e).despawn());
+        loaded.clear();
+    }
+    if !map.visible {
+        return;
+    }
+
+    let (tf, proj) = match cameras.iter().next() {
+        Some(camera) => camera,
+        None => return,
+    };
+    let half = Vec2::new(proj.right, proj.top) * proj.scale;
+    let chunk_size = CHUNK_SIZE as f32 * TILE_SIZE;
+    let center = tf.translation.truncate();
+    let min = ((center - half) / chunk_size).floor().as_ivec2() - IVec2::ONE;
+    let max = ((center + half) / chunk_size).floor().as_ivec2() + IVec2::ONE;
+    if (max - min).max_element() > MAX_VISIBLE_CHUNKS {
+        return;
+    }
+
+    let in_view = |chunk: IVec2| chunk.cmpge(min).all() && chunk.cmple(max).all();
+    for (entity, chunk) in chunks.iter() {
+        if !in_view(chunk.0) {
+            commands.entity(entity).despawn();
+            loaded.remove(&chunk.0);
+        }
+    }
+    for x in min.x..=max.x {
+        for y in min.y..=max.y {
+            let chunk = IVec2::new(x, y);
+            if loaded.insert(chunk) {
+                spawn_chunk(&mut commands, map.as_ref(), world.as_ref(), chunk);
+            }
+        }
+    }
+}
+
+// Only writes on change, any write respawns every chunk. Locked while there's track on the map
+pub fn terrain_ui(ui: &mut egui::Ui, map: &mut ResMut<TerrainMap>, locked: bool) {
+    ui.add_enabled_ui(!locked, |ui| {
+        ui.horizontal(|ui| {
+            ui.label("Map seed");
+            let mut seed = map.seed;
+            ui.add(egui::DragValue::new(&mut seed));
+            if ui.button("Random").clicked() {
+                seed = rand::random::<u32>() as u64;
+            }
+            if seed != map.seed {
+                map.seed = seed;
+            }
+        });
+    });
+    let mut visible = map.visible;
+    if ui.checkbox(&mut visible, "Show terrain").changed() {
+        map.visible = visible;
+    }
+    ui.label("Water needs a bridge, forests and mountains cost more.");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(seed: u64) -> TerrainMap {
+        TerrainMap {
+            seed,
+            ..TerrainMap::default()
+        }
+    }
+
+    fn tiles() -> impl Iterator<Item = TileIndex> {
+        (-40..40).flat_map(|x| (-40..40).map(move |y| (x, y)))
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_terrain() {
+        let (a, b) = (map(42), map(42));
+        for tile in tiles() {
+            assert_eq!(a.terrain_at(tile), b.terrain_at(tile), "{:?}", tile);
+        }
+    }
+
+    #[test]
+    fn different_seeds_give_different_terrain() {
+        let (a, b) = (map(42), map(43));
+        assert!(tiles().any(|tile| a.terrain_at(tile) != b.terrain_at(tile)));
+    }
+}
@@ -1,8 +1,8 @@
-use bevy::prelude::*;
 use bevy::utils::HashSet;
 use bevy_mod_picking::Hover;
-use bevy_prototype_lyon::prelude::tess::geom::{CubicBezierSegment, Point};
+use bevy_prototype_lyon::prelude::tess::geom::CubicBezierSegment;
 use petgraph::prelude::DiGraphMap;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::ops::Mul;
 use std::sync::atomic::{AtomicUsize, Ordering};
@@ -12,7 +12,9 @@ use super::*;
 pub type TrackID = usize;
 static NEXT_TRACK_ID: AtomicUsize = AtomicUsize::new(0);
 
-#[derive(Clone, Copy, Default, Hash, PartialOrd, PartialEq, Ord, Eq)]
+#[derive(
+    Debug, Clone, Copy, Default, Hash, PartialOrd, PartialEq, Ord, Eq, Serialize, Deserialize,
+)]
 pub struct TrackDirection(bool);
 impl TrackDirection {
     pub const POS: TrackDirection = TrackDirection(true);
@@ -67,10 +69,50 @@ impl Mul<TrackDirection> for TrackDirection {
     }
 }
 
+const CURVATURE_SAMPLES: usize = 32;
+// Below this a track counts as straight, float noise would otherwise give huge radii
+const STRAIGHT_CURVATURE: f32 = 1e-5;
+
+// Signed curvature at t, the inverse of the turning radius
+pub fn curvature(curve: &CubicBezierSegment<f32>, t: f32) -> f32 {
+    let (p0, p1, p2, p3) = (
+        curve.from.to_vector(),
+        curve.ctrl1.to_vector(),
+        curve.ctrl2.to_vector(),
+        curve.to.to_vector(),
+    );
+    let first = curve.derivative(t);
+    let second = (p2 - p1 * 2. + p0) * (6. * (1. - t)) + (p3 - p2 * 2. + p1) * (6. * t);
+
+    let speed = first.length();
+    if speed < f32::EPSILON {
+        return 0.;
+    }
+    first.cross(second) / speed.powi(3)
+}
+
+// Fastest speed that keeps lateral acceleration through the tightest point in bounds
+fn safe_speed(curvature: f32) -> f32 {
+    if curvature > 0. {
+        (MAX_LATERAL_ACC / curvature).sqrt().min(MAX_TRAIN_SPEED)
+    } else {
+        MAX_TRAIN_SPEED
+    }
+}
+
 pub struct TrackData {
     pub segment: TrackSegment,
     pub curve: CubicBezierSegment<f32>,
     pub length: f32,
+    pub max_curvature: f32,
+    // None on straights
+    pub min_radius: Option<f32>,
+    pub max_speed: f32,
+    pub name: String,
+    pub speed_limit: Option<f32>,
+    pub electrified: bool,
+    // What building it cost, refunds are a share of this
+    pub paid: f32,
 }
 
 impl TrackData {
@@ -82,6 +124,12 @@ impl TrackData {
         self.segment.end.tile
     }
 
+    // Curvature limit combined with any posted limit
+    pub fn limit(&self) -> f32 {
+        self.speed_limit
+            .map_or(self.max_speed, |limit| limit.min(self.max_speed))
+    }
+
     pub fn get_pos(&self, direction: TrackDirection) -> TrackPos {
         if direction.is_pos() {
             self.segment.end
@@ -93,17 +141,26 @@ impl TrackData {
 
 impl From<TrackSegment> for TrackData {
     fn from(segment: TrackSegment) -> Self {
-        let (start, ctrl1, ctrl2, end) = segment.control_points();
-        let curve = CubicBezierSegment {
-            from: Point::new(start.x, start.y),
-            ctrl1: Point::new(ctrl1.x, ctrl1.y),
-            ctrl2: Point::new(ctrl2.x, ctrl2.y),
-            to: Point::new(end.x, end.y),
+        let curve = segment.curve();
+        let max_curvature = (0..=CURVATURE_SAMPLES)
+            .map(|i| curvature(&curve, i as f32 / CURVATURE_SAMPLES as f32).abs())
+            .fold(0., f32::max);
+        let max_curvature = if max_curvature < STRAIGHT_CURVATURE {
+            0.
+        } else {
+            max_curvature
         };
         Self {
             segment,
             curve,
             length: curve.approximate_length(0.1),
+            max_curvature,
+            min_radius: (max_curvature > 0.).then(|| 1. / max_curvature),
+            max_speed: safe_speed(max_curvature),
+            name: String::new(),
+            speed_limit: None,
+            electrified: false,
+            paid: 0.,
         }
     }
 }
@@ -112,6 +169,9 @@ impl From<TrackSegment> for TrackData {
 pub struct Network {
     pathing_graph: DiGraphMap<TrackPos, TrackEdge>,
     pub tracks: HashMap<TrackID, TrackData>,
+    pub crossings: HashMap<CrossingID, Crossing>,
+    // Dead end nodes trains arrive at, keyed by the node at the end of the track
+    pub buffer_stops: HashSet<TrackPos>,
 }
 
 impl Network {
@@ -129,14 +189,77 @@ impl Network {
 
     pub fn add_track(&mut self, segment: TrackSegment) -> TrackID {
         let id = NEXT_TRACK_ID.fetch_add(1, Ordering::SeqCst);
+        self.add_edges(id, &segment);
+
+        let data = TrackData::from(segment);
+        let crossings: Vec<Crossing> = self
+            .tracks
+            .iter()
+            .filter(|(_, other_data)| other_data.segment.layer == segment.layer)
+            .flat_map(|(other, other_data)| {
+                find_crossings(
+                    segment.layer,
+                    (id, &data.curve),
+                    (*other, &other_data.curve),
+                )
+            })
+            .collect();
+        for crossing in crossings {
+            self.crossings.insert(Crossing::next_id(), crossing);
+        }
+        self.tracks.insert(id, data);
+        self.prune_buffer_stops();
+
+        id
+    }
+
+    // One-way tracks only get the edge for their allowed direction, both nodes
+    // still exist so placement can connect to either end
+    fn add_edges(&mut self, id: TrackID, segment: &TrackSegment) {
+        for node in [
+            segment.start,
+            segment.start.inverse(),
+            segment.end,
+            segment.end.inverse(),
+        ] {
+            self.pathing_graph.add_node(node);
+        }
+        if segment.allows(TrackDirection::POS) {
+            self.pathing_graph
+                .add_edge(segment.start, segment.end.inverse(), TrackEdge::pos(id));
+        }
+        if segment.allows(TrackDirection::NEG) {
+            self.pathing_graph
+                .add_edge(segment.end, segment.start.inverse(), TrackEdge::neg(id));
+        }
+    }
+
+    fn remove_edges(&mut self, segment: &TrackSegment) {
         self.pathing_graph
-            .add_edge(segment.start, segment.end.inverse(), TrackEdge::pos(id));
+            .remove_edge(segment.start, segment.end.inverse());
         self.pathing_graph
-            .add_edge(segment.end, segment.start.inverse(), TrackEdge::neg(id));
+            .remove_edge(segment.end, segment.start.inverse());
+    }
 
-        self.tracks.insert(id, TrackData::from(segment));
+    pub fn set_one_way(&mut self, id: TrackID, one_way: Option<TrackDirection>) {
+        let segment = match self.tracks.get_mut(&id) {
+            Some(track) => {
+                track.segment.one_way = one_way;
+                track.segment
+            }
+            None => return,
+        };
+        self.remove_edges(&segment);
+        self.add_edges(id, &segment);
+        self.prune_buffer_stops();
+    }
 
-        id
+    pub fn contains_segment(&self, segment: &TrackSegment) -> bool {
+        self.pathing_graph
+            .contains_edge(segment.start, segment.end.inverse())
+            || self
+                .pathing_graph
+                .contains_edge(segment.end, segment.start.inverse())
     }
 
     pub fn get(&self, id: TrackID) -> Option<&TrackData> {
@@ -150,14 +273,48 @@ impl Network {
     pub fn remove_track(&mut self, id: TrackID) {
         let track = self.tracks.remove(&id);
         if let Some(track) = track {
-            let segment = track.segment;
-            self.pathing_graph
-                .remove_edge(segment.start, segment.end.inverse());
-            self.pathing_graph
-                .remove_edge(segment.end, segment.start.inverse());
+            self.remove_edges(&track.segment);
+            self.crossings.retain(|_, crossing| !crossing.has_track(id));
+            self.prune_buffer_stops();
         }
     }
 
+    // Whether any track starts or ends at this node, facing the same way
+    pub fn has_end(&self, pos: TrackPos) -> bool {
+        self.tracks
+            .values()
+            .any(|track| track.segment.start == pos || track.segment.end == pos)
+    }
+
+    pub fn is_dead_end(&self, pos: TrackPos) -> bool {
+        self.has_end(pos) && self.get_exits(&pos).is_empty()
+    }
+
+    pub fn dead_ends(&self, tile: TileIndex) -> Vec<TrackPos> {
+        (0..8usize)
+            .map(|facing| TrackPos::new(tile, facing.into()))
+            .filter(|pos| self.is_dead_end(*pos))
+            .collect()
+    }
+
+    // Buffer stops only make sense where the line still ends
+    fn prune_buffer_stops(&mut self) {
+        self.buffer_stops = self
+            .buffer_stops
+            .iter()
+            .copied()
+            .filter(|pos| self.is_dead_end(*pos))
+            .collect();
+    }
+
+    pub fn crossings_near(&self, pos: Vec2, radius: f32, layer: TrackLayer) -> Vec<CrossingID> {
+        self.crossings
+            .iter()
+            .filter(|(_, crossing)| crossing.layer == layer && crossing.pos.distance(pos) < radius)
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
     pub fn get_exits(&self, node: &TrackPos) -> Vec<(&TrackEdge, &TrackData)> {
         let node = node.inverse();
         self.pathing_graph
@@ -167,7 +324,7 @@ impl Network {
     }
 }
 
-#[derive(Clone, Copy, Default, Hash, PartialOrd, PartialEq, Ord, Eq)]
+#[derive(Debug, Clone, Copy, Default, Hash, PartialOrd, PartialEq, Ord, Eq)]
 pub struct TrackEdge {
     pub track: TrackID,
     pub direction: TrackDirection,
@@ -187,6 +344,13 @@ impl TrackEdge {
             direction: TrackDirection::NEG,
         }
     }
+
+    pub fn reversed(&self) -> Self {
+        Self {
+            track: self.track,
+            direction: self.direction.inverse(),
+        }
+    }
 }
 
 pub fn setup_network(mut commands: Commands) {
@@ -201,20 +365,30 @@ pub struct NetworkTrack(pub TrackID);
 #[derive(Component)]
 pub struct NetworkNode(pub TileIndex);
 
+#[derive(Component)]
+pub struct NetworkCrossing(pub CrossingID);
+
+#[derive(Component)]
+pub struct NetworkBufferStop;
+
 pub fn extract_network_to_mesh(
     mut commands: Commands,
     network: Res<Network>,
     events: EventReader<NetworkRenderEvent>,
     tracks: Query<Entity, With<NetworkTrack>>,
     nodes: Query<Entity, With<NetworkNode>>,
+    crossings: Query<Entity, With<NetworkCrossing>>,
+    buffer_stops: Query<Entity, With<NetworkBufferStop>>,
 ) {
     if !events.is_empty() {
         tracks.for_each(|e| commands.entity(e).despawn());
         nodes.for_each(|e| commands.entity(e).despawn());
+        crossings.for_each(|e| commands.entity(e).despawn());
+        buffer_stops.for_each(|e| commands.entity(e).despawn());
 
         let mut nodes = HashSet::new();
         network.tracks.iter().for_each(|(id, track)| {
-            draw_track(&mut commands, *id, &track.segment);
+            draw_track(&mut commands, *id, &track.segment, track.electrified);
             nodes.insert(track.start_tile());
             nodes.insert(track.end_tile());
         });
@@ -222,18 +396,75 @@ pub fn extract_network_to_mesh(
         nodes.iter().for_each(|node| {
             draw_node(&mut commands, *node);
         });
+
+        network.crossings.iter().for_each(|(id, crossing)| {
+            draw_crossing(&mut commands, *id, crossing);
+        });
+
+        network.buffer_stops.iter().for_each(|pos| {
+            commands
+                .spawn_bundle(buffer_stop_shape(*pos, Color::RED))
+                .insert(NetworkBufferStop);
+        });
     }
 }
 
-pub struct TrackPlacementEvent(pub TrackSegment);
+// A batch of segments placed as a single action
+pub struct TrackPlacementEvent(pub Vec<TrackSegment>);
+
+// Why the last batch was refused, cleared once something is placed
+#[derive(Default)]
+pub struct PlacementStatus(pub Option<String>);
 
 pub fn place_tracks(
     mut events: EventReader<TrackPlacementEvent>,
     mut network: ResMut<Network>,
     mut render: EventWriter<NetworkRenderEvent>,
+    terrain: Res<TerrainMap>,
+    world: Res<WorldConfig>,
+    mode: Res<GameMode>,
+    costs: Res<CostModel>,
+    mut finances: ResMut<Finances>,
+    mut status: ResMut<PlacementStatus>,
 ) {
-    for TrackPlacementEvent(segment) in events.iter() {
-        network.add_track(*segment);
+    for TrackPlacementEvent(segments) in events.iter() {
+        if !segments
+            .iter()
+            .all(|segment| world.contains_segment(segment))
+        {
+            status.0 = Some("Can't build outside the map.".to_string());
+            continue;
+        }
+        if segments.iter().any(|segment| terrain.blocks(segment)) {
+            status.0 = Some("Track over water needs a bridge.".to_string());
+            continue;
+        }
+        let paid: Vec<f32> = if *mode == GameMode::Budget {
+            costs.segment_costs(network.as_ref(), terrain.as_ref(), segments)
+        } else {
+            vec![0.; segments.len()]
+        };
+        let cost: f32 = paid.iter().sum();
+        if cost > 0. {
+            if cost > finances.balance {
+                status.0 = Some(format!("Can't afford ${:.0}.", cost));
+                continue;
+            }
+            finances.build(cost);
+        }
+        if status.0.is_some() {
+            status.0 = None;
+        }
+        for (segment, paid) in segments.iter().zip(paid) {
+            // A second copy would share the first one's graph edges
+            if network.contains_segment(segment) {
+                continue;
+            }
+            let id = network.add_track(*segment);
+            if let Some(track) = network.tracks.get_mut(&id) {
+                track.paid = paid;
+            }
+        }
         render.send(NetworkRenderEvent);
     }
 }
@@ -241,15 +472,66 @@ pub fn place_tracks(
 pub fn remove_tracks(
     mut network: ResMut<Network>,
     tracks: Query<(&Hover, &NetworkTrack)>,
-    mouse_buttons: Res<Input<MouseButton>>,
+    actions: Actions,
     mut render: EventWriter<NetworkRenderEvent>,
+    mode: Res<GameMode>,
+    costs: Res<CostModel>,
+    mut finances: ResMut<Finances>,
 ) {
-    if mouse_buttons.pressed(MouseButton::Right) {
+    if actions.pressed(Action::Remove) {
         tracks.for_each(|(h, track)| {
             if h.hovered() {
+                if let Some(data) = network.get(track.0) {
+                    let refund = costs.refund(data);
+                    if *mode == GameMode::Budget && refund > 0. {
+                        finances.build(-refund);
+                    }
+                }
                 network.remove_track(track.0);
                 render.send(NetworkRenderEvent);
             }
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bevy_prototype_lyon::prelude::tess::geom::Point;
+
+    use super::*;
+
+    fn bezier(points: [(f32, f32); 4]) -> CubicBezierSegment<f32> {
+        let [from, ctrl1, ctrl2, to] = points.map(|(x, y)| Point::new(x, y));
+        CubicBezierSegment {
+            from,
+            ctrl1,
+            ctrl2,
+            to,
+        }
+    }
+
+    #[test]
+    fn straight_has_no_curvature() {
+        let curve = bezier([(0., 0.), (0., 10.), (0., 20.), (0., 30.)]);
+        for t in [0., 0.25, 0.5, 1.] {
+            assert_eq!(curvature(&curve, t), 0.);
+        }
+    }
+
+    #[test]
+    fn quarter_circle_curvature_matches_radius() {
+        // Standard cubic approximation of a quarter circle of radius 100
+        let k = 55.23;
+        let curve = bezier([(0., 0.), (0., k), (100. - k, 100.), (100., 100.)]);
+        let measured = curvature(&curve, 0.5);
+        // Turning right is clockwise, so negative
+        assert!(measured < 0.);
+        assert!((measured.abs() - 0.01).abs() < 0.0002, "{}", measured);
+    }
+
+    #[test]
+    fn degenerate_curve_is_flat() {
+        let curve = bezier([(5., 5.); 4]);
+        assert_eq!(curvature(&curve, 0.5), 0.);
+    }
+}
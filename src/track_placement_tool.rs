@@ -1,9 +1,15 @@
-use std::{f32::consts::PI, ops::Index};
-
-use bevy::prelude::*;
+use std::{
+    f32::consts::{PI, SQRT_2},
+    ops::Index,
+};
 
 use super::*;
 
+// Tightest turn the radius slider allows
+const MIN_RADIUS: f32 = 2.5;
+// Tiles the gap between parallel tracks may change by through a turn
+const PARALLEL_DRIFT: f32 = 0.25;
+
 #[derive(Component)]
 pub struct TrackGhost;
 
@@ -12,11 +18,20 @@ pub struct PlacementState {
     start: Option<TileIndex>,
     facing_options: [bool; 8],
     facing: Option<Octant>,
+    pending: Vec<TrackSegment>,
+    dragging: bool,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct TrackParams {
     pub radius: f32,
+    pub track_count: usize,
+    pub spacing: i32,
+    pub parallel_left: bool,
+    pub full_path: bool,
+    pub layer: TrackLayer,
+    pub geometry: TrackGeometry,
+    pub one_way: bool,
 }
 
 fn in_direction(start: Vec2, facing: Octant, end: Vec2) -> bool {
@@ -25,7 +40,19 @@ fn in_direction(start: Vec2, facing: Octant, end: Vec2) -> bool {
 }
 
 impl TrackParams {
-    fn get_turn(&self, facing: Octant, dir: f32) -> Vec2 {
+    // Segment between two travel positions using the selected layer, geometry and direction
+    pub fn segment(&self, start: TrackPos, end: TrackPos) -> TrackSegment {
+        let segment = TrackSegment::from_directed(start, end)
+            .with_layer(self.layer)
+            .with_geometry(self.geometry);
+        if self.one_way {
+            segment.one_way_from(start)
+        } else {
+            segment
+        }
+    }
+
+    pub fn get_turn(&self, facing: Octant, dir: f32) -> Vec2 {
         let dir = -dir.signum();
         let unit = octant_to_unit(facing);
         let center = unit.perp() * self.radius * dir;
@@ -39,23 +66,91 @@ impl TrackParams {
         start_facing: Octant,
         target_tile: TileIndex,
         allow_bends: bool,
-    ) -> Vec<TrackPos> {
-        let tracks = self.place_vec_tracks(
+    ) -> Vec<Vec<TrackPos>> {
+        let paths = self.place_vec_tracks(
             tile_to_vec(start_tile),
             start_facing,
             tile_to_vec(target_tile),
             allow_bends,
         );
-        let tracks: Vec<TrackPos> = tracks.into_iter().map(|t| t.into()).collect();
-        tracks
+        paths
+            .into_iter()
+            .map(|tracks| tracks.into_iter().map(|t| t.into()).collect())
+            .collect()
+    }
+
+    // Whole diagonal steps between parallel tracks, the count closest to the spacing
+    fn diagonal_steps(&self) -> i32 {
+        (self.spacing as f32 / SQRT_2).round().max(1.) as i32
+    }
+
+    // Offset square to the facing, so every turn shares its centre with the main track's. Straights
+    // are spaced by whole tiles and diagonals by whole √2 tile steps, the same for every neighbour
+    fn parallel_offset(&self, facing: Octant, index: usize) -> TileVec {
+        let side = if self.parallel_left {
+            facing.perp().inverse()
+        } else {
+            facing.perp()
+        };
+        let step = (octant_to_unit(side) * SQRT_2).round().as_ivec2();
+        let steps = if facing.0 % 2 == 1 {
+            self.diagonal_steps()
+        } else {
+            self.spacing
+        };
+        step * steps * index as i32
     }
 
+    // Why the parallel tracks can't follow a path, if they can't. Through a turn the gap goes from
+    // the straight spacing to the diagonal one, so only spacings a whole number of diagonal steps
+    // nearly matches keep the radii in step, and inside tracks need room for their tighter turn
+    pub fn parallel_problem(&self, path: &[TrackPos]) -> Option<&'static str> {
+        if self.track_count < 2 {
+            return None;
+        }
+        let drift = (self.diagonal_steps() as f32 * SQRT_2 - self.spacing as f32).abs();
+        let inner = self.radius - (self.spacing * (self.track_count as i32 - 1)) as f32;
+        for pair in path.windows(2) {
+            let (from, to) = (pair[0].facing, pair[1].facing);
+            if from == to {
+                continue;
+            }
+            if drift > PARALLEL_DRIFT {
+                return Some("Spacing can't stay even through turns");
+            }
+            let inside = (to == from.right()) != self.parallel_left;
+            if inside && inner < MIN_RADIUS {
+                return Some("Turn too tight for the parallel tracks");
+            }
+        }
+        None
+    }
+
+    // First path follows the cursor, the rest run parallel to one side of it
     fn place_vec_tracks(
         &self,
         start_tile: TileVec,
         start_facing: Octant,
         target_tile: TileVec,
         allow_bends: bool,
+    ) -> Vec<Vec<(TileVec, Octant)>> {
+        let tracks = self.place_vec_path(start_tile, start_facing, target_tile, allow_bends);
+        (0..self.track_count.max(1))
+            .map(|index| {
+                tracks
+                    .iter()
+                    .map(|(tile, facing)| (*tile + self.parallel_offset(*facing, index), *facing))
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn place_vec_path(
+        &self,
+        start_tile: TileVec,
+        start_facing: Octant,
+        target_tile: TileVec,
+        allow_bends: bool,
     ) -> Vec<(TileVec, Octant)> {
         let mut tracks = Vec::new();
         if start_tile == target_tile {
@@ -191,6 +286,49 @@ impl ArrowHighlighter {
     }
 }
 
+impl ArrowHighlighter {
+    pub fn highlight(
+        &self,
+        arrows: &mut Query<(&mut Visibility, &mut DrawMode), With<Arrow>>,
+        options: &[bool; 8],
+        best: usize,
+    ) {
+        let arrows = arrows
+            .get_many_mut(self.arrows)
+            .expect("Highlighter arrow entities missing");
+
+        arrows.into_iter().enumerate().for_each(|(i, arrow)| {
+            let (mut vis, mut dm) = arrow;
+            vis.is_visible = options[i];
+            if let DrawMode::Fill(FillMode { color, .. }) = dm.as_mut() {
+                *color = if best == i {
+                    Self::HIGHLIGHT_COLOR
+                } else {
+                    Self::NORMAL_COLOR
+                }
+            }
+        });
+    }
+}
+
+// Get closest allowed direction to the mouse vector
+pub fn closest_facing(options: &[bool; 8], mouse_vec: Vec2) -> usize {
+    let (best, _) =
+        options
+            .iter()
+            .enumerate()
+            .fold((0, f32::INFINITY), |(best, to_beat), (index, flag)| {
+                let unit = octant_to_unit(index);
+                let diff = mouse_vec.normalize_or_zero().distance_squared(unit);
+                if *flag && diff < to_beat {
+                    (index, diff)
+                } else {
+                    (best, to_beat)
+                }
+            });
+    best
+}
+
 impl Index<usize> for ArrowHighlighter {
     type Output = Entity;
 
@@ -200,7 +338,16 @@ impl Index<usize> for ArrowHighlighter {
 }
 
 pub fn setup_track_placement(mut commands: Commands) {
-    let params = TrackParams { radius: 6. };
+    let params = TrackParams {
+        radius: 6.,
+        track_count: 1,
+        spacing: 3,
+        parallel_left: false,
+        full_path: false,
+        layer: TrackLayer::Ground,
+        geometry: TrackGeometry::Standard,
+        one_way: false,
+    };
 
     commands.insert_resource(params);
     ArrowHighlighter::spawn(&mut commands);
@@ -216,28 +363,47 @@ pub fn track_placement_tool(
     mut arrows: Query<(&mut Visibility, &mut DrawMode), With<Arrow>>,
     mut events: EventWriter<TrackPlacementEvent>,
 
+    mut params: ResMut<TrackParams>,
     network: Res<Network>,
-    params: Res<TrackParams>,
     mouse_pos: Res<MousePos>,
-    mouse_buttons: Res<Input<MouseButton>>,
-    keys: Res<Input<KeyCode>>,
+    actions: Actions,
     ghosts: Query<Entity, With<TrackGhost>>,
+    mut ctx: ResMut<EguiContext>,
+    terrain: Res<TerrainMap>,
+    world: Res<WorldConfig>,
+    mode: Res<GameMode>,
+    costs: Res<CostModel>,
+    finances: Res<Finances>,
 ) {
-    let shift = keys.any_pressed([KeyCode::LShift, KeyCode::RShift]);
+    let shift = actions.pressed(Action::Alternate);
+    for (action, layer) in [
+        (Action::Bridge, TrackLayer::Bridge),
+        (Action::Tunnel, TrackLayer::Tunnel),
+    ] {
+        if actions.just_pressed(action) {
+            params.layer = if params.layer == layer {
+                TrackLayer::Ground
+            } else {
+                layer
+            };
+        }
+    }
     let (mut arrows_tf, mut arrows_vis, arrow_highlighter) = arrow_highlighter.single_mut();
 
     ghosts.for_each(|e| commands.entity(e).despawn());
     if let Some(mouse_pos) = mouse_pos.0 {
-        if mouse_buttons.just_pressed(MouseButton::Right) {
+        if actions.just_pressed(Action::Remove) {
             placement.start = None;
             placement.facing = None;
+            placement.pending.clear();
+            placement.dragging = false;
             arrows_vis.is_visible = false;
         }
 
         let mouse_tile = pos_to_tile(mouse_pos);
         match (placement.start, placement.facing) {
             (None, _) => {
-                if mouse_buttons.just_pressed(MouseButton::Left) {
+                if actions.just_pressed(Action::Place) {
                     placement.start = Some(mouse_tile);
 
                     if shift {
@@ -258,64 +424,127 @@ pub fn track_placement_tool(
                 let start_pos = tile_to_center(start_tile);
                 let mouse_vec = mouse_pos - start_pos;
 
-                // Get closest direction to mouse_angle
-                let (best, _) = placement.facing_options.iter().enumerate().fold(
-                    (0, f32::INFINITY),
-                    |(best, to_beat), (index, flag)| {
-                        let unit = octant_to_unit(index);
-                        let diff = mouse_vec.normalize_or_zero().distance_squared(unit);
-                        if *flag && diff < to_beat {
-                            (index, diff)
-                        } else {
-                            (best, to_beat)
-                        }
-                    },
-                );
-
-                let arrows = arrows
-                    .get_many_mut(arrow_highlighter.arrows)
-                    .expect("Highlighter arrow entities missing");
-
-                arrows.into_iter().enumerate().for_each(|(i, arrow)| {
-                    let (mut vis, mut dm) = arrow;
-                    vis.is_visible = placement.facing_options[i];
-                    if let DrawMode::Fill(FillMode { color, .. }) = dm.as_mut() {
-                        *color = if best == i {
-                            ArrowHighlighter::HIGHLIGHT_COLOR
-                        } else {
-                            ArrowHighlighter::NORMAL_COLOR
-                        }
-                    }
-                });
+                let best = closest_facing(&placement.facing_options, mouse_vec);
+                arrow_highlighter.highlight(&mut arrows, &placement.facing_options, best);
                 arrows_vis.is_visible = true;
 
-                if mouse_buttons.just_pressed(MouseButton::Left) {
+                if actions.just_pressed(Action::Place) {
                     placement.facing = Some(best.into());
                     arrows_vis.is_visible = false;
                 }
             }
             (Some(start_tile), Some(facing)) => {
-                let mut path = PathBuilder::new();
-                let tracks = params.place_tracks(start_tile, facing, mouse_tile, shift);
-                if tracks.is_empty() {
-                    return;
-                }
-                let segments: Vec<TrackSegment> = tracks
-                    .windows(2)
-                    .map(|pair| TrackSegment::from_directed(pair[0], pair[1]))
+                let parallel = params.place_tracks(start_tile, facing, mouse_tile, shift);
+                let tracks = &parallel[0];
+                let segments: Vec<Vec<TrackSegment>> = parallel
+                    .iter()
+                    .map(|tracks| {
+                        tracks
+                            .windows(2)
+                            .map(|pair| params.segment(pair[0], pair[1]))
+                            .collect()
+                    })
                     .collect();
-                for segment in segments.iter() {
-                    track_path(&mut path, segment);
+
+                // Price what the next click or release would lay
+                let laying: Vec<TrackSegment> = if params.full_path {
+                    placement
+                        .pending
+                        .iter()
+                        .chain(segments.iter().flatten())
+                        .copied()
+                        .collect()
+                } else {
+                    segments
+                        .iter()
+                        .filter_map(|chain| chain.first())
+                        .copied()
+                        .collect()
+                };
+                let cost = costs.batch_cost(network.as_ref(), terrain.as_ref(), &laying);
+                let budget = *mode == GameMode::Budget;
+                let outside = !laying.iter().all(|s| world.contains_segment(s));
+                let blocked = laying.iter().any(|segment| terrain.blocks(segment));
+                let uneven = params.parallel_problem(tracks);
+                let affordable = !outside
+                    && !blocked
+                    && uneven.is_none()
+                    && (!budget || cost <= finances.balance);
+
+                if !tracks.is_empty() || !placement.pending.is_empty() {
+                    let mut path = PathBuilder::new();
+                    for segment in placement.pending.iter().chain(segments.iter().flatten()) {
+                        track_path(&mut path, segment);
+                    }
+                    let color = if affordable {
+                        Color::GRAY
+                    } else {
+                        Color::rgb(0.6, 0.2, 0.2)
+                    };
+                    commands
+                        .spawn_bundle(build_path(path, color, 4., 0.1))
+                        .insert(TrackGhost);
+
+                    if outside {
+                        egui::show_tooltip_at_pointer(
+                            ctx.ctx_mut(),
+                            egui::Id::new("track_cost"),
+                            |ui| ui.label("Outside the map"),
+                        );
+                    } else if blocked {
+                        egui::show_tooltip_at_pointer(
+                            ctx.ctx_mut(),
+                            egui::Id::new("track_cost"),
+                            |ui| ui.label("Needs a bridge over water"),
+                        );
+                    } else if let Some(problem) = uneven {
+                        egui::show_tooltip_at_pointer(
+                            ctx.ctx_mut(),
+                            egui::Id::new("track_cost"),
+                            |ui| ui.label(problem),
+                        );
+                    } else if budget {
+                        egui::show_tooltip_at_pointer(
+                            ctx.ctx_mut(),
+                            egui::Id::new("track_cost"),
+                            |ui| ui.label(format!("${:.0}", cost)),
+                        );
+                    }
                 }
-                commands
-                    .spawn_bundle(build_path(path, Color::GRAY, 4., 0.1))
-                    .insert(TrackGhost);
 
-                if mouse_buttons.just_pressed(MouseButton::Left) {
+                if params.full_path {
+                    // Lay the whole planned chain at once, Space while dragging adds a waypoint
+                    let end = tracks.last().copied();
+                    if actions.just_pressed(Action::Place) {
+                        placement.dragging = true;
+                    }
+                    if !placement.dragging {
+                        return;
+                    }
+
+                    // Only take on the dragged stretch when it can be laid, a refused release
+                    // keeps the earlier waypoints to carry on from
+                    let released = actions.just_released(Action::Place);
+                    if (released || actions.just_pressed(Action::Waypoint)) && affordable {
+                        placement.pending.extend(segments.into_iter().flatten());
+                        if let Some(end) = end {
+                            placement.start = Some(end.tile);
+                            placement.facing = Some(end.facing);
+                        }
+                    }
+                    if released {
+                        placement.dragging = false;
+                        if affordable && !placement.pending.is_empty() {
+                            let batch = std::mem::take(&mut placement.pending);
+                            events.send(TrackPlacementEvent(batch));
+                        }
+                    }
+                } else if !tracks.is_empty() && affordable && actions.just_pressed(Action::Place) {
                     placement.start = Some(tracks[1].tile);
                     placement.facing = Some(tracks[1].facing);
 
-                    events.send(TrackPlacementEvent(segments[0]));
+                    let batch = segments.iter().map(|chain| chain[0]).collect();
+                    events.send(TrackPlacementEvent(batch));
                 }
             }
         };
@@ -332,4 +561,6 @@ pub fn cleanup_track_placement(
     ghosts.for_each(|g| commands.entity(g).despawn());
     placement.facing = None;
     placement.start = None;
+    placement.pending.clear();
+    placement.dragging = false;
 }
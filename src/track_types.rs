@@ -1,6 +1,11 @@
+use bevy_prototype_lyon::prelude::tess::geom::{CubicBezierSegment, Point};
+use serde::{Deserialize, Serialize};
+
 use super::*;
 
-#[derive(Debug, Clone, Copy, Default, Hash, PartialOrd, PartialEq, Ord, Eq)]
+#[derive(
+    Debug, Clone, Copy, Default, Hash, PartialOrd, PartialEq, Ord, Eq, Serialize, Deserialize,
+)]
 pub struct TrackPos {
     pub tile: TileIndex,
     pub facing: Octant,
@@ -17,6 +22,10 @@ impl TrackPos {
             facing: self.facing.inverse(),
         }
     }
+
+    pub fn offset(&self, by: TileVec) -> Self {
+        (tile_to_vec(self.tile) + by, self.facing).into()
+    }
 }
 
 impl From<(IVec2, Octant)> for TrackPos {
@@ -25,24 +34,127 @@ impl From<(IVec2, Octant)> for TrackPos {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+// Tracks only meet crossings on their own layer
+#[derive(Debug, Clone, Copy, Default, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TrackLayer {
+    Tunnel,
+    #[default]
+    Ground,
+    Bridge,
+}
+
+impl TrackLayer {
+    pub fn z(&self) -> f32 {
+        match self {
+            TrackLayer::Tunnel => 6.,
+            TrackLayer::Ground => 10.,
+            TrackLayer::Bridge => 14.,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TrackGeometry {
+    #[default]
+    Standard,
+    // Curvature eases in from zero at both ends
+    Transition,
+}
+
+// Handles meeting at the tangent intersection flatten both ends of a turn, S-bends
+// have no intersection so their handles are stretched instead
+fn transition_handles(
+    start: Vec2,
+    start_dir: Vec2,
+    end: Vec2,
+    end_dir: Vec2,
+) -> Option<(f32, f32)> {
+    let diff = end - start;
+    let cross = start_dir.perp_dot(end_dir);
+    if cross.abs() < 0.01 {
+        if diff.perp_dot(start_dir).abs() < 0.01 {
+            return None;
+        }
+        let distance = diff.length();
+        return Some((distance / 2., distance / 2.));
+    }
+
+    let start_mag = diff.perp_dot(end_dir) / cross;
+    let end_mag = diff.perp_dot(start_dir) / cross;
+    if start_mag > 0. && end_mag > 0. {
+        Some((start_mag, end_mag))
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct TrackSegment {
     pub start: TrackPos,
     pub end: TrackPos,
+    #[serde(default)]
+    pub layer: TrackLayer,
+    #[serde(default)]
+    pub geometry: TrackGeometry,
+    // Only travel in this direction is allowed
+    #[serde(default)]
+    pub one_way: Option<TrackDirection>,
 }
 
 impl TrackSegment {
-    pub fn from_directed(start: TrackPos, end: TrackPos) -> Self {
+    pub fn new(start: TrackPos, end: TrackPos) -> Self {
+        Self {
+            start,
+            end,
+            layer: TrackLayer::Ground,
+            geometry: TrackGeometry::Standard,
+            one_way: None,
+        }
+        .sort()
+    }
+
+    // Only allow travel leaving the given end
+    pub fn one_way_from(mut self, start: TrackPos) -> Self {
+        self.one_way = Some(if self.start == start {
+            TrackDirection::POS
+        } else {
+            TrackDirection::NEG
+        });
+        self
+    }
+
+    pub fn allows(&self, direction: TrackDirection) -> bool {
+        self.one_way.map_or(true, |one_way| one_way == direction)
+    }
+
+    pub fn with_layer(mut self, layer: TrackLayer) -> Self {
+        self.layer = layer;
+        self
+    }
+
+    pub fn with_geometry(mut self, geometry: TrackGeometry) -> Self {
+        self.geometry = geometry;
+        self
+    }
+
+    // Same layer and geometry between different ends
+    pub fn with_ends(&self, start: TrackPos, end: TrackPos) -> Self {
         Self {
             start,
-            end: end.inverse(),
+            end,
+            ..*self
         }
         .sort()
     }
 
+    pub fn from_directed(start: TrackPos, end: TrackPos) -> Self {
+        Self::new(start, end.inverse())
+    }
+
     fn sort(mut self) -> Self {
         if self.start.tile > self.end.tile {
             std::mem::swap(&mut self.start, &mut self.end);
+            self.one_way = self.one_way.map(|direction| direction.inverse());
         }
         self
     }
@@ -51,16 +163,48 @@ impl TrackSegment {
         let start_pos = tile_to_center(self.start.tile);
         let end_pos = tile_to_center(self.end.tile);
 
-        let ctrl_mag = start_pos.distance(end_pos) / 3.;
         let start_ctrl = octant_to_unit(self.start.facing);
         let end_ctrl = octant_to_unit(self.end.facing);
+
+        let ctrl_mag = start_pos.distance(end_pos) / 3.;
+        let (start_mag, end_mag) = match self.geometry {
+            TrackGeometry::Standard => (ctrl_mag, ctrl_mag),
+            TrackGeometry::Transition => {
+                transition_handles(start_pos, start_ctrl, end_pos, end_ctrl)
+                    .unwrap_or((ctrl_mag, ctrl_mag))
+            }
+        };
         (
             start_pos,
-            start_pos + start_ctrl * ctrl_mag,
-            end_pos + end_ctrl * ctrl_mag,
+            start_pos + start_ctrl * start_mag,
+            end_pos + end_ctrl * end_mag,
             end_pos,
         )
     }
+
+    pub fn curve(&self) -> CubicBezierSegment<f32> {
+        let (start, ctrl1, ctrl2, end) = self.control_points();
+        CubicBezierSegment {
+            from: Point::new(start.x, start.y),
+            ctrl1: Point::new(ctrl1.x, ctrl1.y),
+            ctrl2: Point::new(ctrl2.x, ctrl2.y),
+            to: Point::new(end.x, end.y),
+        }
+    }
+
+    // Tiles the track passes through, sampled a few times per tile
+    pub fn tiles(&self) -> Vec<TileIndex> {
+        let curve = self.curve();
+        let steps = (curve.approximate_length(0.1) / (TILE_SIZE / 4.)).ceil() as usize + 1;
+        let mut tiles: Vec<TileIndex> = (0..=steps)
+            .map(|step| {
+                let point = curve.sample(step as f32 / steps as f32);
+                pos_to_tile(Vec2::new(point.x, point.y))
+            })
+            .collect();
+        tiles.dedup();
+        tiles
+    }
 }
 
 // impl From<(TileIndex, TileIndex, &TrackEdge)> for TrackSegment {
@@ -78,3 +222,35 @@ impl TrackSegment {
 //         }
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn turn_handles_meet_at_the_tangent_intersection() {
+        let handles = transition_handles(Vec2::ZERO, Vec2::Y, Vec2::new(10., 10.), Vec2::NEG_X);
+        assert_eq!(handles, Some((10., 10.)));
+    }
+
+    #[test]
+    fn straight_has_no_transition() {
+        let handles = transition_handles(Vec2::ZERO, Vec2::Y, Vec2::new(0., 10.), Vec2::NEG_Y);
+        assert_eq!(handles, None);
+    }
+
+    #[test]
+    fn s_bend_stretches_both_handles() {
+        let end = Vec2::new(4., 10.);
+        let (start_mag, end_mag) =
+            transition_handles(Vec2::ZERO, Vec2::Y, end, Vec2::NEG_Y).unwrap();
+        assert_eq!(start_mag, end.length() / 2.);
+        assert_eq!(end_mag, end.length() / 2.);
+    }
+
+    #[test]
+    fn turn_behind_the_start_is_rejected() {
+        let handles = transition_handles(Vec2::ZERO, Vec2::Y, Vec2::new(10., -10.), Vec2::NEG_X);
+        assert_eq!(handles, None);
+    }
+}
@@ -1,3 +1,4 @@
+use bevy::ecs::system::EntityCommands;
 use bevy::utils::FloatOrd;
 use bevy_mod_picking::{Hover, PickableBundle};
 use bevy_prototype_lyon::prelude::tess::{geom::CubicBezierSegment, math::Point};
@@ -117,8 +118,9 @@ pub fn train_placement_tool(
     network: Res<Network>,
     mouse_pos: Res<MousePos>,
     train: Query<Entity, With<TrainGhost>>,
-    mouse_buttons: Res<Input<MouseButton>>,
-    keys: Res<Input<KeyCode>>,
+    driven: Query<(Entity, &Hover), With<Driving>>,
+    actions: Actions,
+    mut active: ResMut<ActiveTrain>,
     mut writer: EventWriter<TrainPlacementEvent>,
 ) {
     train.for_each(|e| commands.entity(e).despawn());
@@ -129,14 +131,22 @@ pub fn train_placement_tool(
     let mouse_point = Point::new(mouse_pos.x, mouse_pos.y);
     let nearest = find_nearest_track(network.as_ref(), mouse_point, 100.);
 
+    // Clicking a driven train takes control of it instead of placing another
+    if actions.just_pressed(Action::Place) {
+        if let Some((entity, _)) = driven.iter().find(|(_, h)| h.hovered()) {
+            active.0 = Some(entity);
+            return;
+        }
+    }
+
     if let Some((track, sample, point, _)) = nearest {
         draw_train_ghost(
             &mut commands,
             Vec2::new(point.x, point.y),
             Color::rgba(0.0, 0.0, 1.0, 0.95),
         );
-        if mouse_buttons.just_pressed(MouseButton::Left) {
-            let shift = keys.any_pressed([KeyCode::LShift, KeyCode::RShift]);
+        if actions.just_pressed(Action::Place) {
+            let shift = actions.pressed(Action::Alternate);
             writer.send(TrainPlacementEvent {
                 track,
                 sample,
@@ -155,9 +165,66 @@ pub struct Train {
     track_edge: TrackEdge,
     sample: f32,
     speed: f32,
+    next_edge: Option<TrackEdge>,
+    pub electric: bool,
+    pub kind: TrainType,
+    pub acceleration: f32,
+    // Brakes to a stand and ignores the controls until released
+    pub held: bool,
+    // Which way the front faces relative to travel, turned with every reversal
+    front: TrackDirection,
+    dwell: f32,
+    route: Vec<TrackEdge>,
+    // Dispatched track the train stops on once the route runs out
+    destination: Option<TrackID>,
+}
+
+#[derive(Component)]
+pub struct Derailed;
+
+#[derive(Default)]
+pub struct TrainSettings {
+    pub derailments: bool,
+    pub electric: bool,
+    pub train_type: usize,
 }
 
 impl Train {
+    pub fn new(track_edge: TrackEdge, sample: f32, kind: TrainType, electric: bool) -> Self {
+        Self {
+            track_edge,
+            sample,
+            speed: 0.,
+            next_edge: None,
+            electric,
+            kind,
+            acceleration: 0.,
+            held: false,
+            front: TrackDirection::POS,
+            dwell: 0.,
+            route: Vec::new(),
+            destination: None,
+        }
+    }
+
+    pub fn track(&self) -> TrackID {
+        self.track_edge.track
+    }
+
+    // The track end the train has run up against, if any
+    pub fn arrived_at(&self, network: &Network) -> Option<TrackPos> {
+        if self.sample < 1. {
+            return None;
+        }
+        network
+            .get_data(self.track_edge)
+            .map(|track| track.get_pos(self.direction()))
+    }
+
+    pub fn sample(&self) -> f32 {
+        self.sample
+    }
+
     pub fn direction(&self) -> TrackDirection {
         self.track_edge.direction
     }
@@ -166,45 +233,143 @@ impl Train {
         self.sample = 1. - self.sample;
         self.track_edge.direction = self.track_edge.direction.inverse();
         self.speed = -self.speed;
+        self.next_edge = None;
+        self.front = self.front.inverse();
+    }
+
+    // Driven trains keep the driver facing the front, so they turn around with the train
+    pub fn reverse(&mut self, driving: Option<&mut Driving>) {
+        self.flip();
+        if let Some(driving) = driving {
+            driving.0 = driving.0.inverse();
+        }
+    }
+
+    pub fn front(&self) -> TrackDirection {
+        self.front
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    pub fn route(&self) -> &[TrackEdge] {
+        &self.route
+    }
+
+    pub fn destination(&self) -> Option<TrackID> {
+        self.destination
+    }
+
+    pub fn arrived(&self) -> bool {
+        self.destination == Some(self.track())
+    }
+
+    // Resuming at the destination hands the train back to self-driving
+    pub fn release(&mut self) {
+        self.held = false;
+        if self.arrived() {
+            self.destination = None;
+        }
+    }
+
+    // Plans a route to the target track, reversing at dead ends where needed
+    pub fn plan_route(&mut self, network: &Network, target: TrackID) -> bool {
+        let electric = self.electric;
+        let route = find_train_route(network, self.track_edge, self.sample, target, |data| {
+            !electric || data.electrified
+        });
+        self.next_edge = None;
+        match route {
+            Some(route) => {
+                self.route = route;
+                self.destination = Some(target);
+                true
+            }
+            None => {
+                self.route.clear();
+                self.destination = None;
+                false
+            }
+        }
+    }
+
+    pub fn accelerate(&mut self, delta: f32, throttle: f32, brake: f32, curvature: f32) {
+        self.acceleration = self
+            .kind
+            .acceleration(self.speed, throttle, brake, curvature);
+        self.speed = (self.speed + self.acceleration * delta).clamp(0., self.kind.max_speed);
+    }
+
+    // Power or brake towards the target speed without overshooting it
+    pub fn approach(&mut self, target: f32, delta: f32, curvature: f32) {
+        if self.speed > target {
+            self.accelerate(delta, 0., 1., curvature);
+            self.speed = self.speed.max(target);
+        } else {
+            self.accelerate(delta, 1., 0., curvature);
+            self.speed = self.speed.min(target);
+        }
     }
 }
 
+pub fn spawn_train<'w, 's, 'a>(
+    commands: &'a mut Commands<'w, 's>,
+    network: &Network,
+    train: Train,
+) -> EntityCommands<'w, 's, 'a> {
+    let point = network
+        .get_data(train.track_edge)
+        .map_or(Point::zero(), |track| train_point(track, &train));
+    let pos = Vec2::new(point.x, point.y);
+
+    let circle = shapes::Circle {
+        radius: 16.,
+        ..default()
+    };
+
+    let mut ec = commands.spawn_bundle(GeometryBuilder::build_as(
+        &circle,
+        DrawMode::Fill(FillMode::color(Color::BLUE)),
+        Transform::from_translation(pos.extend(20.)),
+    ));
+    ec.insert_bundle(PickableBundle::default()).insert(train);
+    ec
+}
+
 pub fn place_train(
     mut commands: Commands,
     mut events: EventReader<TrainPlacementEvent>,
     network: Res<Network>,
+    settings: Res<TrainSettings>,
+    mut active: ResMut<ActiveTrain>,
 ) {
     for event in events.iter() {
-        let track = network.get(event.track);
-        if let Some(track) = track {
-            let point = track.curve.sample(event.sample);
-            let pos = Vec2::new(point.x, point.y);
-
-            let circle = shapes::Circle {
-                radius: 16.,
-                ..default()
-            };
-
-            let mut ec = commands.spawn_bundle(GeometryBuilder::build_as(
-                &circle,
-                DrawMode::Fill(FillMode::color(Color::BLUE)),
-                Transform::from_translation(pos.extend(20.)),
-            ));
-
-            ec.insert_bundle(PickableBundle::default()).insert(Train {
-                track_edge: TrackEdge::pos(event.track),
-                sample: event.sample,
-                speed: 0.,
-            });
+        if network.get(event.track).is_some() {
+            let train = Train::new(
+                TrackEdge::pos(event.track),
+                event.sample,
+                TRAIN_TYPES[settings.train_type],
+                settings.electric,
+            );
+            let mut ec = spawn_train(&mut commands, network.as_ref(), train);
             if !event.shift {
                 ec.insert(Driving(TrackDirection::POS));
+                active.0 = Some(ec.id());
             }
         }
     }
 }
 
 #[derive(Component)]
-pub struct Driving(TrackDirection);
+pub struct Driving(pub TrackDirection);
+
+// The one driven train that answers to the controls, the rest hold position
+#[derive(Default)]
+pub struct ActiveTrain(pub Option<Entity>);
+
+#[derive(Component)]
+pub struct ActiveMarker;
 
 fn move_along(track: &TrackData, train: &mut Train, amount: f32) -> f32 {
     let scaled = amount / track.length;
@@ -216,10 +381,42 @@ fn move_along(track: &TrackData, train: &mut Train, amount: f32) -> f32 {
     delta
 }
 
+// Electric trains can only run under wires
+fn train_exits<'a>(
+    network: &'a Network,
+    train: &Train,
+    node: &TrackPos,
+) -> Vec<(&'a TrackEdge, &'a TrackData)> {
+    network
+        .get_exits(node)
+        .into_iter()
+        .filter(|(_, data)| !train.electric || data.electrified)
+        .collect()
+}
+
+fn train_curvature(track: &TrackData, train: &Train) -> f32 {
+    let t = if train.direction().is_pos() {
+        train.sample
+    } else {
+        1. - train.sample
+    };
+    curvature(&track.curve, t).abs()
+}
+
+fn train_point(track: &TrackData, train: &Train) -> Point {
+    if train.direction().is_pos() {
+        track.curve.sample(train.sample)
+    } else {
+        track.curve.sample(1. - train.sample)
+    }
+}
+
 fn update_train<F>(
+    entity: Entity,
     train: &mut Train,
     tf: &mut Transform,
     network: &Network,
+    occupancy: &mut CrossingOccupancy,
     delta: f32,
     mut choose_track: F,
 ) where
@@ -227,11 +424,12 @@ fn update_train<F>(
 {
     let data = network.get_data(train.track_edge);
     if let Some(mut track_data) = data {
+        let (start_data, start_edge, start_sample) = (track_data, train.track_edge, train.sample);
         let mut speed = train.speed * delta;
         while speed > 0. {
             if train.sample >= 1.0 {
                 let node = track_data.get_pos(train.direction());
-                let nodes = network.get_exits(&node);
+                let nodes = train_exits(network, train, &node);
 
                 if !nodes.is_empty() {
                     let (edge, next) = nodes[choose_track(&nodes[..])];
@@ -250,37 +448,69 @@ fn update_train<F>(
             speed -= delta;
         }
 
-        let point = if train.direction().is_pos() {
-            track_data.curve.sample(train.sample)
-        } else {
-            track_data.curve.sample(1. - train.sample)
-        };
+        let mut point = train_point(track_data, train);
+
+        // Wait short of any crossing held by another train
+        let pos = Vec2::new(point.x, point.y);
+        let (track, layer) = (train.track(), track_data.segment.layer);
+        if !occupancy.try_enter(network, entity, track, pos, layer) {
+            train.track_edge = start_edge;
+            train.sample = start_sample;
+            train.speed = 0.;
+            point = train_point(start_data, train);
+        }
 
         tf.translation.x = point.x;
         tf.translation.y = point.y;
     }
 }
 
-const TRAIN_ACC: f32 = 200.;
+// Only touch the draw mode on change so the shape isn't rebuilt every frame
+fn set_train_color(mode: &mut Mut<DrawMode>, color: Color) {
+    if !matches!(**mode, DrawMode::Fill(ref fill) if fill.color == color) {
+        **mode = DrawMode::Fill(FillMode::color(color));
+    }
+}
+
+// How far past the speed limit a driven train can go before derailing
+const DERAIL_MARGIN: f32 = 1.25;
 pub fn drive_trains(
+    mut commands: Commands,
     time: Res<Time>,
-    keys: Res<Input<KeyCode>>,
+    actions: Actions,
+    active: Res<ActiveTrain>,
     network: Res<Network>,
-    mut trains: Query<(&mut Train, &mut Transform, &mut Driving)>,
+    settings: Res<TrainSettings>,
+    mut occupancy: ResMut<CrossingOccupancy>,
+    mut trains: Query<(
+        Entity,
+        &mut Train,
+        &mut Transform,
+        &mut Driving,
+        &mut DrawMode,
+    )>,
 ) {
-    trains.for_each_mut(|(mut train, mut tf, mut driving)| {
-        if keys.pressed(KeyCode::W) {
-            train.speed =
-                (train.speed + time.delta_seconds() * TRAIN_ACC * driving.0.signum()).clamp(-300., 300.);
-        }
-        if keys.pressed(KeyCode::S) {
-            train.speed =
-                (train.speed - time.delta_seconds() * TRAIN_ACC * driving.0.signum()).clamp(-300., 300.);
-        }
-        if train.speed < 0. {
-            train.flip();
-            driving.0 = driving.0.inverse();
+    trains.for_each_mut(|(entity, mut train, mut tf, mut driving, mut mode)| {
+        // Forward pushes towards the driver's front and backward away, braking then reversing
+        let controlled = active.0 == Some(entity);
+        let pushed =
+            actions.pressed(Action::Forward) as i32 - actions.pressed(Action::Backward) as i32;
+        let mut effort = pushed as f32 * driving.0.signum();
+        if train.held || !controlled {
+            effort = -1.;
+        } else if effort < 0. && train.speed <= 0. {
+            train.reverse(Some(&mut *driving));
+            effort = -effort;
         }
+        let bend = network
+            .get_data(train.track_edge)
+            .map_or(0., |track_data| train_curvature(track_data, &train));
+        train.accelerate(
+            time.delta_seconds(),
+            effort.max(0.),
+            (-effort).max(0.),
+            bend,
+        );
 
         let track_data = network.get_data(train.track_edge);
         let curr_direction = train.direction();
@@ -288,16 +518,18 @@ pub fn drive_trains(
             let curr_end = track_data.get_pos(curr_direction);
             let end_vec = IVec2::from(curr_end.tile).as_vec2();
             update_train(
+                entity,
                 &mut train,
                 &mut tf,
                 network.as_ref(),
+                occupancy.as_mut(),
                 time.delta_seconds(),
                 |exits| {
                     let end = track_data.get_pos(curr_direction);
                     let mut facing = end.facing.inverse();
 
-                    let left = keys.pressed(KeyCode::A);
-                    let right = keys.pressed(KeyCode::D);
+                    let left = controlled && actions.pressed(Action::SteerLeft);
+                    let right = controlled && actions.pressed(Action::SteerRight);
 
                     if left {
                         facing = facing.perp().inverse();
@@ -327,33 +559,224 @@ pub fn drive_trains(
                 },
             );
         }
+
+        // Warn when over the limit, optionally derail when well over it
+        if let Some(track_data) = network.get_data(train.track_edge) {
+            if settings.derailments && train.speed > track_data.limit() * DERAIL_MARGIN {
+                train.speed = 0.;
+                commands.entity(entity).remove::<Driving>().insert(Derailed);
+                set_train_color(&mut mode, Color::DARK_GRAY);
+            } else if train.speed > track_data.limit() {
+                set_train_color(&mut mode, Color::ORANGE);
+            } else {
+                set_train_color(&mut mode, Color::BLUE);
+            }
+        }
     });
 }
 
+// Steps through the driven trains in entity order
+pub fn cycle_driven(
+    mut ctx: ResMut<EguiContext>,
+    actions: Actions,
+    mut active: ResMut<ActiveTrain>,
+    trains: Query<Entity, With<Driving>>,
+) {
+    if !actions.just_pressed(Action::CycleDriven) || ctx.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+    active.0 = next_entity(active.0, trains.iter());
+}
+
+// Rings the active train, dropping it once it's gone or no longer driven
+pub fn mark_active_train(
+    mut commands: Commands,
+    mut active: ResMut<ActiveTrain>,
+    trains: Query<&Transform, (With<Driving>, Without<ActiveMarker>)>,
+    mut markers: Query<(Entity, &mut Transform), With<ActiveMarker>>,
+) {
+    let pos = match active.0.map(|e| trains.get(e)) {
+        Some(Ok(tf)) => tf.translation.truncate(),
+        Some(Err(_)) => {
+            active.0 = None;
+            return;
+        }
+        None => {
+            markers.for_each(|(e, _)| commands.entity(e).despawn());
+            return;
+        }
+    };
+
+    match markers.iter_mut().next() {
+        Some((_, mut tf)) => tf.translation = pos.extend(tf.translation.z),
+        None => {
+            let ring = shapes::Circle {
+                radius: 22.,
+                ..default()
+            };
+            commands
+                .spawn_bundle(GeometryBuilder::build_as(
+                    &ring,
+                    DrawMode::Stroke(StrokeMode {
+                        color: Color::WHITE,
+                        options: StrokeOptions::default().with_line_width(3.),
+                    }),
+                    Transform::from_translation(pos.extend(21.)),
+                ))
+                .insert(ActiveMarker);
+        }
+    }
+}
+
+// Fastest the train can go now and still slow for every limit within its stopping distance.
+// Follows the route, then the planned next track, then the slowest exit at each node.
+// Dispatched trains stop halfway along their destination track
+fn braking_target(network: &Network, train: &Train, track_data: &TrackData) -> f32 {
+    let mut target = track_data.limit();
+    if train.arrived() {
+        let distance = (0.5 - train.sample) * track_data.length;
+        return target.min(train.kind.braking_speed(0., distance));
+    }
+    let horizon = train
+        .kind
+        .stopping_distance(track_data.limit().max(train.speed));
+    let mut distance = (1. - train.sample) * track_data.length;
+    let mut steps = train
+        .route
+        .iter()
+        .skip_while(|step| Some(**step) != train.next_edge)
+        .skip(1);
+    let mut edge = train.next_edge;
+    loop {
+        let (ahead, data) = match edge.and_then(|e| network.get_data(e).map(|data| (e, data))) {
+            Some(next) => next,
+            // Dead end or planned reversal, so stop at the end of what's known
+            None => return target.min(train.kind.braking_speed(0., distance)),
+        };
+        if Some(ahead.track) == train.destination {
+            let distance = distance + 0.5 * data.length;
+            return target.min(train.kind.braking_speed(0., distance));
+        }
+        target = target.min(train.kind.braking_speed(data.limit(), distance));
+        distance += data.length;
+        if distance > horizon {
+            return target;
+        }
+
+        let exits = train_exits(network, train, &data.get_pos(ahead.direction));
+        edge = match steps.next() {
+            Some(step) => exits.iter().any(|(exit, _)| *exit == step).then_some(*step),
+            None => exits
+                .iter()
+                .min_by_key(|(_, data)| FloatOrd(data.limit()))
+                .map(|(exit, _)| **exit),
+        };
+    }
+}
+
 pub fn update_trains(
     time: Res<Time>,
     network: Res<Network>,
+    end_of_line: Res<EndOfLine>,
     mut rand: ResMut<StdRng>,
-    mut trains: Query<(&mut Train, &mut Transform), Without<Driving>>,
+    mut occupancy: ResMut<CrossingOccupancy>,
+    mut trains: Query<(Entity, &mut Train, &mut Transform), (Without<Driving>, Without<Derailed>)>,
 ) {
-    trains.for_each_mut(|(mut train, mut tf)| {
-        train.speed = (train.speed + time.delta_seconds() * TRAIN_ACC).min(300.);
+    trains.for_each_mut(|(entity, mut train, mut tf)| {
+        let track_data = match network.get_data(train.track_edge) {
+            Some(track_data) => track_data,
+            None => return,
+        };
+
+        let end = track_data.get_pos(train.direction());
+        let options = train_exits(network.as_ref(), &train, &end);
+
+        // Follow the route, a reversed copy of the current edge is a planned reversal
+        let reversing = train.route.first() == Some(&train.track_edge.reversed());
+        if let Some(step) = train.route.first().copied() {
+            if options.iter().any(|(edge, _)| **edge == step) {
+                train.next_edge = Some(step);
+            } else if reversing {
+                train.next_edge = None;
+            } else {
+                train.route.clear();
+                train.destination = None;
+            }
+        }
+
+        // Hold once stopped at the destination until released from the inspector
+        if train.arrived() && train.sample >= 0.5 && !train.held {
+            train.held = true;
+            train.speed = 0.;
+        }
+
+        // Wait at buffer stops and planned reversals then head back the way it came
+        if train.sample >= 1. && (options.is_empty() || reversing) {
+            let can_reverse = track_data.segment.allows(train.direction().inverse());
+            let at_buffer = end_of_line.reverse && network.buffer_stops.contains(&end);
+            if can_reverse && !train.held && (reversing || at_buffer) {
+                train.dwell += time.delta_seconds();
+                if train.dwell >= end_of_line.pause {
+                    train.dwell = 0.;
+                    train.flip();
+                    if reversing {
+                        train.route.remove(0);
+                    }
+                }
+            }
+            return;
+        }
+
+        // Pick the next track early so the train can brake for it
+        let planned = train
+            .next_edge
+            .map_or(false, |next| options.iter().any(|(edge, _)| **edge == next));
+        if !planned && !reversing {
+            train.next_edge = if options.is_empty() {
+                None
+            } else {
+                Some(*options[rand.gen_range(0..options.len())].0)
+            };
+        }
+
+        let target = if train.held {
+            0.
+        } else {
+            braking_target(network.as_ref(), &train, track_data)
+        };
+        let bend = train_curvature(track_data, &train);
+        train.approach(target, time.delta_seconds(), bend);
+
+        let (edge, next_edge) = (train.track_edge, train.next_edge);
         update_train(
+            entity,
             &mut train,
             &mut tf,
             network.as_ref(),
+            occupancy.as_mut(),
             time.delta_seconds(),
-            |exits| rand.gen_range(0..exits.len()),
+            |exits| {
+                exits
+                    .iter()
+                    .position(|(edge, _)| Some(**edge) == next_edge)
+                    .unwrap_or_else(|| rand.gen_range(0..exits.len()))
+            },
         );
+        if train.track_edge != edge {
+            train.next_edge = None;
+            if train.route.first() == Some(&train.track_edge) {
+                train.route.remove(0);
+            }
+        }
     });
 }
 
 pub fn remove_trains(
     mut commands: Commands,
     trains: Query<(Entity, &Hover), With<Train>>,
-    mouse_buttons: Res<Input<MouseButton>>,
+    actions: Actions,
 ) {
-    if mouse_buttons.pressed(MouseButton::Right) {
+    if actions.pressed(Action::Remove) {
         trains.for_each(|(e, h)| {
             if h.hovered() {
                 commands.entity(e).despawn();
@@ -361,3 +784,49 @@ pub fn remove_trains(
         });
     }
 }
+
+pub fn train_stats_ui(
+    mut ctx: ResMut<EguiContext>,
+    trains: Query<(&Train, Option<&Wagons>, Option<&Driving>, Option<&Derailed>)>,
+) {
+    if trains.is_empty() {
+        return;
+    }
+
+    egui::Window::new("Trains").show(ctx.ctx_mut(), |ui| {
+        egui::Grid::new("train_stats").striped(true).show(ui, |ui| {
+            ui.label("Type");
+            ui.label("Speed");
+            ui.label("Accel");
+            ui.label("Stopping");
+            ui.label("Load");
+            ui.end_row();
+
+            for (train, wagons, driving, derailed) in trains.iter() {
+                let mode = match (driving, derailed) {
+                    (_, Some(_)) => "derailed",
+                    (Some(_), _) => "driven",
+                    _ if train.arrived() && train.held => "arrived",
+                    _ => "auto",
+                };
+                ui.label(format!("{} ({})", train.kind.name, mode));
+                ui.label(format!("{:.0}", train.speed()));
+                ui.label(format!("{:+.0}", train.acceleration));
+                ui.label(format!(
+                    "{:.1} tiles",
+                    train.kind.stopping_distance(train.speed()) / TILE_SIZE
+                ));
+                match wagons.and_then(|wagons| wagons.load.map(|load| (wagons, load))) {
+                    Some((wagons, load)) => ui.label(format!(
+                        "{}/{} {}",
+                        load.amount,
+                        wagons.capacity,
+                        load.cargo.name()
+                    )),
+                    None => ui.label("Empty"),
+                };
+                ui.end_row();
+            }
+        });
+    });
+}
@@ -0,0 +1,108 @@
+use super::*;
+
+// Extra resistance per unit of curvature, so tight curves drag more
+const CURVE_RESISTANCE: f32 = 1000.;
+
+// Forces are scaled so that force / mass gives px/s²
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrainType {
+    pub name: &'static str,
+    pub mass: f32,
+    // Tractive effort at standstill, falls off as power / speed once moving
+    pub tractive_effort: f32,
+    pub power: f32,
+    pub max_speed: f32,
+    pub braking: f32,
+    pub rolling_resistance: f32,
+    pub drag: f32,
+    // Units of cargo the wagons hold
+    pub capacity: u32,
+    // Money per second while in service
+    pub running_cost: f32,
+}
+
+pub const TRAIN_TYPES: [TrainType; 4] = [
+    TrainType {
+        name: "Passenger",
+        mass: 200.,
+        tractive_effort: 50_000.,
+        power: 3_600_000.,
+        max_speed: MAX_TRAIN_SPEED,
+        braking: 250.,
+        rolling_resistance: 5.,
+        drag: 0.05,
+        capacity: 150,
+        running_cost: 2.,
+    },
+    TrainType {
+        name: "Express",
+        mass: 300.,
+        tractive_effort: 60_000.,
+        power: 7_200_000.,
+        max_speed: MAX_TRAIN_SPEED,
+        braking: 200.,
+        rolling_resistance: 5.,
+        drag: 0.04,
+        capacity: 100,
+        running_cost: 4.,
+    },
+    TrainType {
+        name: "Freight",
+        mass: 1000.,
+        tractive_effort: 80_000.,
+        power: 4_000_000.,
+        max_speed: 200.,
+        braking: 100.,
+        rolling_resistance: 8.,
+        drag: 0.1,
+        capacity: 400,
+        running_cost: 3.,
+    },
+    TrainType {
+        name: "Shunter",
+        mass: 80.,
+        tractive_effort: 20_000.,
+        power: 1_000_000.,
+        max_speed: 120.,
+        braking: 200.,
+        rolling_resistance: 5.,
+        drag: 0.02,
+        capacity: 40,
+        running_cost: 1.,
+    },
+];
+
+impl Default for TrainType {
+    fn default() -> Self {
+        TRAIN_TYPES[0]
+    }
+}
+
+impl TrainType {
+    pub fn tractive_force(&self, speed: f32) -> f32 {
+        self.tractive_effort.min(self.power / speed.max(1.))
+    }
+
+    pub fn resistance(&self, speed: f32, curvature: f32) -> f32 {
+        if speed <= 0. {
+            return 0.;
+        }
+        self.mass * (self.rolling_resistance + CURVE_RESISTANCE * curvature.abs())
+            + self.drag * speed * speed
+    }
+
+    // Throttle and brake are both in 0..=1
+    pub fn acceleration(&self, speed: f32, throttle: f32, brake: f32, curvature: f32) -> f32 {
+        (throttle * self.tractive_force(speed) - self.resistance(speed, curvature)) / self.mass
+            - brake * self.braking
+    }
+
+    // Fastest speed from which the train can still slow to target within distance
+    pub fn braking_speed(&self, target: f32, distance: f32) -> f32 {
+        (target.powi(2) + 2. * self.braking * distance.max(0.)).sqrt()
+    }
+
+    pub fn stopping_distance(&self, speed: f32) -> f32 {
+        speed.powi(2) / (2. * self.braking)
+    }
+}
@@ -1,10 +1,14 @@
 use std::{f32::consts::PI, ops::Add};
 
+use serde::{Deserialize, Serialize};
+
 use super::*;
 
 pub type TileIndex = (i32, i32);
 
-#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(
+    Debug, Copy, Clone, Default, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize,
+)]
 pub struct Octant(pub i8);
 
 #[allow(dead_code)]
@@ -32,6 +36,10 @@ impl Octant {
     pub fn perp(&self) -> Self {
         Self((self.0 + 2) % 8)
     }
+
+    pub fn mirror(&self) -> Self {
+        Self((8 - self.0) % 8)
+    }
 }
 
 impl Add for Octant {
@@ -76,6 +84,38 @@ pub fn angle_to_unit(angle: f32) -> Vec2 {
 
 pub type TileVec = IVec2;
 
+// Rotate clockwise in 45° steps by walking the tile an eighth of the way round its square ring
+// per step. Quarter turns are exact, odd steps swap straight and diagonal offsets of the same
+// ring so tiles that touch keep touching
+pub fn rotate_tile_vec(vec: TileVec, eighths: i8) -> TileVec {
+    let ring = vec.x.abs().max(vec.y.abs());
+    if ring == 0 {
+        return vec;
+    }
+    // Position along the ring clockwise from the top left corner
+    let (x, y) = (vec.x, vec.y);
+    let along = if y == ring && x < ring {
+        x + ring
+    } else if x == ring && y > -ring {
+        3 * ring - y
+    } else if y == -ring && x > -ring {
+        5 * ring - x
+    } else {
+        7 * ring + y
+    };
+    let along = (along + eighths as i32 * ring).rem_euclid(8 * ring);
+    match along / (2 * ring) {
+        0 => TileVec::new(along - ring, ring),
+        1 => TileVec::new(ring, 3 * ring - along),
+        2 => TileVec::new(5 * ring - along, -ring),
+        _ => TileVec::new(-ring, along - 7 * ring),
+    }
+}
+
+pub fn mirror_tile_vec(vec: TileVec) -> TileVec {
+    TileVec::new(-vec.x, vec.y)
+}
+
 // Convert from pos (Vec2)
 pub fn pos_to_vec(pos: Vec2) -> Vec2 {
     (pos / TILE_SIZE - 0.5).round()
@@ -107,3 +147,17 @@ pub fn tile_to_vec(tile: TileIndex) -> TileVec {
 pub fn tile_to_center(tile: TileIndex) -> Vec2 {
     tile_vec_to_center(tile_to_vec(tile))
 }
+
+// The entity after the current one, wrapping around. Ids get reused so this isn't spawn order
+pub fn next_entity(
+    current: Option<Entity>,
+    entities: impl Iterator<Item = Entity>,
+) -> Option<Entity> {
+    let mut entities: Vec<Entity> = entities.collect();
+    entities.sort();
+    match current.and_then(|e| entities.iter().position(|t| *t == e)) {
+        Some(index) => entities.get(index + 1).or_else(|| entities.first()),
+        None => entities.first(),
+    }
+    .copied()
+}
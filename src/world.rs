@@ -0,0 +1,80 @@
+use super::*;
+
+// The playable area in tiles, centred on the origin
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WorldConfig {
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Default for WorldConfig {
+    fn default() -> Self {
+        Self {
+            width: 200,
+            height: 200,
+        }
+    }
+}
+
+impl WorldConfig {
+    pub fn min_tile(&self) -> TileVec {
+        IVec2::new(-self.width / 2, -self.height / 2)
+    }
+
+    pub fn max_tile(&self) -> TileVec {
+        self.min_tile() + IVec2::new(self.width, self.height) - IVec2::ONE
+    }
+
+    pub fn contains(&self, tile: TileIndex) -> bool {
+        let tile = tile_to_vec(tile);
+        tile.cmpge(self.min_tile()).all() && tile.cmple(self.max_tile()).all()
+    }
+
+    pub fn contains_segment(&self, segment: &TrackSegment) -> bool {
+        segment.tiles().into_iter().all(|tile| self.contains(tile))
+    }
+
+    // World space corners
+    pub fn rect(&self) -> (Vec2, Vec2) {
+        (
+            tile_vec_to_pos(self.min_tile()),
+            tile_vec_to_pos(self.max_tile() + IVec2::ONE),
+        )
+    }
+}
+
+#[derive(Component)]
+pub struct WorldBorder;
+
+pub fn draw_world_border(
+    mut commands: Commands,
+    world: Res<WorldConfig>,
+    borders: Query<Entity, With<WorldBorder>>,
+) {
+    if !world.is_changed() {
+        return;
+    }
+    borders.for_each(|e| commands.entity(e).despawn());
+
+    let (min, max) = world.rect();
+    let mut path = PathBuilder::new();
+    rect_path(&mut path, min, max);
+    commands
+        .spawn_bundle(build_path(path, Color::rgb(0.6, 0.6, 0.6), 6., 5.))
+        .insert(WorldBorder);
+}
+
+// Resizing could leave what's built outside the map
+pub fn world_ui(ui: &mut egui::Ui, world: &mut ResMut<WorldConfig>, locked: bool) {
+    let mut size = **world;
+    ui.add_enabled_ui(!locked, |ui| {
+        ui.add(egui::Slider::new(&mut size.width, 32..=1000).text("World width"));
+        ui.add(egui::Slider::new(&mut size.height, 32..=1000).text("World height"));
+    });
+    if locked {
+        ui.label("Clear the map to change its seed or size.");
+    }
+    if size != **world {
+        **world = size;
+    }
+}